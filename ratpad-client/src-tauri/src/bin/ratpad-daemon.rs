@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use ratpad::serial_client::{send_listener_command, ListenerCommand};
+use ratpad::util::pad_runtime::{default_config_dir, start_runtime, PadRuntime};
+use serde_json::json;
+
+/// Runs the pad services (serial listener, action executor, config store) without the GUI.
+#[derive(Parser)]
+#[command(name = "ratpad-daemon", version)]
+struct Args {
+    /// Directory holding the config file. Defaults to the one the desktop client uses.
+    #[arg(long)]
+    config_dir: Option<PathBuf>,
+
+    /// Don't print events to stdout.
+    #[arg(long, short)]
+    quiet: bool,
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    let runtime = PadRuntime::new(args.config_dir.unwrap_or_else(default_config_dir));
+
+    if !args.quiet {
+        // One JSON object per line, so journald and pipes can follow along.
+        runtime.set_emitter(|event, payload| {
+            println!("{}", json!({"event": event, "payload": payload}));
+        });
+    }
+
+    start_runtime(&runtime);
+
+    tokio::signal::ctrl_c()
+        .await
+        .expect("Unable to listen for shutdown signal");
+    send_listener_command(runtime.clone(), ListenerCommand::Quit);
+}
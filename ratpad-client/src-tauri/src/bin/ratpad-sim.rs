@@ -0,0 +1,99 @@
+use std::{
+    fs::read_to_string,
+    io::{stdin, BufRead},
+    path::PathBuf,
+    process::exit,
+    thread::sleep,
+    time::Duration,
+};
+
+use clap::Parser;
+use ratpad::util::pad_simulator::{key_code, PtyPad, SimulatedPad};
+
+/// A virtual MacroPad speaking the ratpad protocol over a pseudo-terminal.
+///
+/// Reads inputs from the script and then stdin, one per line:
+/// `press <key>`, `rotate <delta>`, `switch <down|up>`, `reconnect`, `state`, `sleep <ms>`, `quit`.
+/// Keys are names (`next`, `action_4`) or codes (0-11).
+#[derive(Parser)]
+#[command(name = "ratpad-sim", version)]
+struct Args {
+    /// Where to keep the pad's db.json. Nothing is persisted without it.
+    #[arg(long)]
+    db: Option<PathBuf>,
+
+    /// Symlink the pty here, for a stable port name.
+    #[arg(long)]
+    link: Option<PathBuf>,
+
+    /// Run these inputs before reading stdin.
+    #[arg(long)]
+    script: Option<PathBuf>,
+}
+
+/// Runs one input line. Returns false once the simulator should stop.
+fn run_input(pad: &PtyPad, line: &str) -> bool {
+    let mut parts = line.split_whitespace();
+    let (Some(input), argument) = (parts.next(), parts.next()) else {
+        return true;
+    };
+
+    match (input, argument) {
+        ("press", Some(key)) => match key_code(key) {
+            Some(code) => pad.send(|p| p.press(code)),
+            None => eprintln!("Unknown key: {key}"),
+        },
+        ("rotate", Some(delta)) => match delta.parse::<i32>() {
+            Ok(delta) => pad.send(|p| p.rotate(delta)),
+            Err(_) => eprintln!("Invalid delta: {delta}"),
+        },
+        ("switch", Some("down")) => pad.send(|p| p.set_encoder_switch(true)),
+        ("switch", Some("up")) => pad.send(|p| p.set_encoder_switch(false)),
+        ("reconnect", None) => {
+            pad.send(|p| p.disconnect());
+            pad.send(|p| p.connect());
+        }
+        ("state", None) => {
+            if let Some(state) = pad.with_pad(
+                |p| serde_json::json!({"mode": p.mode(), "config": p.config(), "typed": p.typed()}),
+            ) {
+                println!("{state}");
+            }
+        }
+        ("sleep", Some(ms)) => sleep(Duration::from_millis(ms.parse().unwrap_or(0))),
+        ("quit", None) => return false,
+        _ => eprintln!("Unknown input: {line}"),
+    }
+    true
+}
+
+fn main() {
+    let args = Args::parse();
+    let pad = match PtyPad::open(SimulatedPad::new(args.db), args.link) {
+        Ok(pad) => pad,
+        Err(error) => {
+            eprintln!("Unable to open pty: {error}");
+            exit(1);
+        }
+    };
+    println!("{}", pad.path().display());
+
+    if let Some(script) = args.script {
+        let inputs = read_to_string(&script).unwrap_or_else(|error| {
+            eprintln!("Unable to read {}: {error}", script.display());
+            exit(1);
+        });
+        for line in inputs.lines() {
+            if !run_input(&pad, line) {
+                return;
+            }
+        }
+    }
+
+    for line in stdin().lock().lines() {
+        let Ok(line) = line else { break };
+        if !run_input(&pad, &line) {
+            break;
+        }
+    }
+}
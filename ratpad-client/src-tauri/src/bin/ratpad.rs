@@ -0,0 +1,329 @@
+use std::{fs::read_to_string, path::PathBuf, process::exit};
+
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use ratpad::util::{
+    command_handler::{CommandTypes, SetColorType},
+    config_format::ConfigFormat,
+    configuration::{AppModeConfig, SyncPolicy},
+    control_socket::send_control_command,
+    input_injection::Delivery,
+    keyboard_layout::{KeyboardLayout, TextFallback},
+    pad_runtime::default_config_dir,
+};
+
+/// Drives a running ratpad client or daemon. Every result is printed as JSON.
+#[derive(Parser)]
+#[command(name = "ratpad", version)]
+struct Cli {
+    /// Directory of the running instance's config file.
+    #[arg(long, global = true)]
+    config_dir: Option<PathBuf>,
+
+    /// Device to target. Defaults to every connected pad, or the first for queries.
+    #[arg(long, short, global = true)]
+    device: Option<String>,
+
+    /// Pretty-print the output.
+    #[arg(long, global = true)]
+    pretty: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List available serial ports.
+    Ports,
+
+    /// Show every known device and its connection.
+    State,
+
+    /// Connect to a pad.
+    Connect {
+        port: String,
+        #[arg(long, default_value_t = 115200)]
+        rate: u32,
+    },
+
+    /// Disconnect from a pad.
+    Disconnect,
+
+    /// Print the local config.
+    Config,
+
+    /// Set one of the navigation colors or the brightness.
+    Color {
+        #[command(subcommand)]
+        color: ColorCommand,
+    },
+
+    /// Manage modes in the local config.
+    Modes {
+        #[command(subcommand)]
+        command: ModesCommand,
+    },
+
+    /// Reverse the last color or mode change, on the pads too.
+    Undo,
+
+    /// Make the last undone change again.
+    Redo,
+
+    /// Talk to the pad directly.
+    Pad {
+        #[command(subcommand)]
+        command: PadCommand,
+    },
+
+    /// Compare or reconcile the local and pad configs.
+    Sync {
+        #[command(subcommand)]
+        command: SyncCommand,
+    },
+
+    /// Turn switching modes to follow the focused window on or off.
+    AutoSwitch {
+        #[arg(action = ArgAction::Set)]
+        enabled: bool,
+    },
+
+    /// Set the host keyboard layout text is typed for.
+    Layout {
+        layout: LayoutArg,
+
+        /// What to do with characters the layout can't type.
+        #[arg(long)]
+        fallback: Option<FallbackArg>,
+    },
+
+    /// Choose whether actions type through the pad or a uinput device on this host.
+    Delivery { delivery: DeliveryArg },
+
+    /// Rewrite the config file as JSON, TOML or YAML.
+    Format { format: FormatArg },
+
+    /// Check a key chord like CTRL+SHIFT+A and list ways to finish its last key.
+    Keys { keys: String },
+
+    /// Manage named sets of colors and modes.
+    Profile {
+        #[command(subcommand)]
+        command: ProfileCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum ColorCommand {
+    Next { r: u32, g: u32, b: u32 },
+    Previous { r: u32, g: u32, b: u32 },
+    Select { r: u32, g: u32, b: u32 },
+    Brightness { value: f64 },
+}
+
+#[derive(Subcommand)]
+enum ModesCommand {
+    /// Write a mode from a JSON file, replacing any mode with the same key.
+    Write { file: PathBuf },
+
+    /// Delete a mode by key.
+    Delete { key: String },
+
+    /// Delete every mode.
+    Clear,
+}
+
+#[derive(Subcommand)]
+enum PadCommand {
+    /// Switch the pad to a mode.
+    SetMode { mode: String },
+
+    /// Return the pad to its mode list.
+    SetHome,
+
+    /// Print the config stored on the pad.
+    Config,
+}
+
+#[derive(Clone, ValueEnum)]
+enum PolicyArg {
+    ClientWins,
+    PadWins,
+}
+
+#[derive(Clone, ValueEnum)]
+enum LayoutArg {
+    Us,
+    Uk,
+    De,
+    Fr,
+}
+
+#[derive(Clone, ValueEnum)]
+enum FallbackArg {
+    UnicodeInput,
+    Skip,
+}
+
+#[derive(Clone, ValueEnum)]
+enum DeliveryArg {
+    PadHid,
+    HostUinput,
+}
+
+#[derive(Clone, ValueEnum)]
+enum FormatArg {
+    Json,
+    Toml,
+    Yaml,
+}
+
+#[derive(Subcommand)]
+enum SyncCommand {
+    /// Show what differs between the local config and the pad.
+    Diff,
+
+    /// Settle the differences in favour of one side.
+    Apply { policy: PolicyArg },
+}
+
+#[derive(Subcommand)]
+enum ProfileCommand {
+    /// List profiles and show which is active.
+    List,
+
+    /// Add an empty profile.
+    Create {
+        name: String,
+    },
+
+    /// Copy a profile under a new name.
+    Clone {
+        source: String,
+        name: String,
+    },
+
+    Rename {
+        name: String,
+        new_name: String,
+    },
+
+    Delete {
+        name: String,
+    },
+
+    /// Switch to a profile and update the connected pads.
+    Activate {
+        name: String,
+    },
+}
+
+fn read_mode(file: &PathBuf) -> Result<AppModeConfig, String> {
+    let data =
+        read_to_string(file).map_err(|e| format!("Unable to read {}: {e}", file.display()))?;
+    serde_json::from_str::<AppModeConfig>(&data).map_err(|e| format!("Invalid mode file: {e}"))
+}
+
+fn to_command(command: Command, device: Option<String>) -> Result<CommandTypes, String> {
+    Ok(match command {
+        Command::Ports => CommandTypes::SerialListPorts { device },
+        Command::State => CommandTypes::SerialGetState { device },
+        Command::Connect { port, rate } => CommandTypes::SerialConnect { port, rate, device },
+        Command::Disconnect => CommandTypes::SerialDisconnect { device },
+        Command::Config => CommandTypes::ConfGetConfig { device },
+        Command::Color { color } => CommandTypes::ConfSetColor {
+            color: match color {
+                ColorCommand::Next { r, g, b } => SetColorType::Next { color: (r, g, b) },
+                ColorCommand::Previous { r, g, b } => SetColorType::Previous { color: (r, g, b) },
+                ColorCommand::Select { r, g, b } => SetColorType::Select { color: (r, g, b) },
+                ColorCommand::Brightness { value } => SetColorType::Brightness { color: value },
+            },
+            device,
+        },
+        Command::Modes { command } => match command {
+            ModesCommand::Write { file } => CommandTypes::ConfWriteMode {
+                mode: read_mode(&file)?,
+                device,
+            },
+            ModesCommand::Delete { key } => CommandTypes::ConfDeleteMode { key, device },
+            ModesCommand::Clear => CommandTypes::ConfClearModes { device },
+        },
+        Command::Undo => CommandTypes::ConfUndo {},
+        Command::Redo => CommandTypes::ConfRedo {},
+        Command::Pad { command } => match command {
+            PadCommand::SetMode { mode } => CommandTypes::PadSetMode { mode, device },
+            PadCommand::SetHome => CommandTypes::PadSetHome { device },
+            PadCommand::Config => CommandTypes::PadGetConfig { device },
+        },
+        Command::Sync { command } => match command {
+            SyncCommand::Diff => CommandTypes::SyncGetDiff { device },
+            SyncCommand::Apply { policy } => CommandTypes::SyncApply {
+                policy: match policy {
+                    PolicyArg::ClientWins => SyncPolicy::ClientWins,
+                    PolicyArg::PadWins => SyncPolicy::PadWins,
+                },
+                device,
+            },
+        },
+        Command::AutoSwitch { enabled } => CommandTypes::ConfSetAutoSwitch { enabled },
+        Command::Layout { layout, fallback } => CommandTypes::ConfSetKeyboardLayout {
+            layout: match layout {
+                LayoutArg::Us => KeyboardLayout::Us,
+                LayoutArg::Uk => KeyboardLayout::Uk,
+                LayoutArg::De => KeyboardLayout::De,
+                LayoutArg::Fr => KeyboardLayout::Fr,
+            },
+            fallback: fallback.map(|fallback| match fallback {
+                FallbackArg::UnicodeInput => TextFallback::UnicodeInput,
+                FallbackArg::Skip => TextFallback::Skip,
+            }),
+        },
+        Command::Delivery { delivery } => CommandTypes::ConfSetDelivery {
+            delivery: match delivery {
+                DeliveryArg::PadHid => Delivery::PadHid,
+                DeliveryArg::HostUinput => Delivery::HostUinput,
+            },
+        },
+        Command::Format { format } => CommandTypes::ConfSetFormat {
+            format: match format {
+                FormatArg::Json => ConfigFormat::Json,
+                FormatArg::Toml => ConfigFormat::Toml,
+                FormatArg::Yaml => ConfigFormat::Yaml,
+            },
+        },
+        Command::Keys { keys } => CommandTypes::KeysComplete { keys },
+        Command::Profile { command } => match command {
+            ProfileCommand::List => CommandTypes::ProfileList {},
+            ProfileCommand::Create { name } => CommandTypes::ProfileCreate { name },
+            ProfileCommand::Clone { source, name } => CommandTypes::ProfileClone { source, name },
+            ProfileCommand::Rename { name, new_name } => {
+                CommandTypes::ProfileRename { name, new_name }
+            }
+            ProfileCommand::Delete { name } => CommandTypes::ProfileDelete { name },
+            ProfileCommand::Activate { name } => CommandTypes::ProfileActivate { name },
+        },
+    })
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let config_dir = cli.config_dir.unwrap_or_else(default_config_dir);
+
+    let result = to_command(cli.command, cli.device)
+        .and_then(|command| send_control_command(&config_dir, &command));
+
+    match result {
+        Ok(output) => {
+            let printed = if cli.pretty {
+                serde_json::to_string_pretty(&output)
+            } else {
+                serde_json::to_string(&output)
+            };
+            println!("{}", printed.expect("Unable to serialize result"));
+        }
+        Err(reason) => {
+            eprintln!("{}", serde_json::json!({ "error": reason }));
+            exit(1);
+        }
+    }
+}
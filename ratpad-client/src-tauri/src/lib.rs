@@ -0,0 +1,4 @@
+pub mod util;
+
+pub use util::ratpad_communication;
+pub use util::serial_client;
@@ -1,92 +1,102 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-mod util;
-
-use std::sync::Mutex;
-use tauri::AppHandle;
+use ratpad::serial_client::SerialEvent;
+use ratpad::util::app_state::ConnectionState;
+use ratpad::util::app_state::DeviceState;
+use ratpad::util::command_handler::execute;
+use ratpad::util::command_handler::CommandReturnTypes;
+use ratpad::util::command_handler::CommandTypes;
+use ratpad::util::pad_runtime::{start_runtime, PadRuntime};
 use tauri::CustomMenuItem;
-use tauri::SystemTray;
-
 use tauri::Manager;
+use tauri::State;
+use tauri::SystemTray;
 use tauri::SystemTrayEvent;
 use tauri::SystemTrayMenu;
 use tauri::SystemTrayMenuItem;
 use tauri::WindowEvent;
-use util::app_state::ApplicationState;
-use util::app_state::ConnectionState;
-use util::command_handler::execute;
-use util::command_handler::CommandReturnTypes;
-use util::command_handler::CommandTypes;
-use util::configuration::AppConfig;
-pub use util::ratpad_communication;
-pub use util::serial_client;
-use util::serial_client::SerialEvent;
-use util::serial_client::{start_serial_listener, ListenerCommand};
+use tokio::task::spawn_blocking;
 
 // Learn more about Tauri commands at https://tauri.app/v1/guides/features/command
 #[tauri::command]
 async fn execute_command(
-    app: AppHandle,
+    runtime: State<'_, PadRuntime>,
     command: CommandTypes,
 ) -> Result<CommandReturnTypes, String> {
-    match execute(app, command) {
-        Ok(res) => Ok(res),
-        Err(reason) => Err(reason.to_string()),
+    let runtime = runtime.inner().clone();
+    // execute() blocks on pad replies, so keep it off the async workers.
+    match spawn_blocking(move || execute(runtime, command)).await {
+        Ok(Ok(res)) => Ok(res),
+        Ok(Err(reason)) => Err(reason),
+        Err(error) => Err(error.to_string()),
     }
 }
 
-fn generate_tray() -> SystemTray {
+fn generate_tray_menu(devices: Vec<DeviceState>) -> SystemTrayMenu {
     let title = CustomMenuItem::new("title".to_string(), "Ratpad Client").disabled();
-    let status = CustomMenuItem::new("status".to_string(), "Status: Disconnected").disabled();
     let toggle = CustomMenuItem::new("toggle".to_string(), "Toggle Window");
-    let menu = SystemTrayMenu::new()
-        .add_item(title)
-        .add_item(status)
-        .add_native_item(SystemTrayMenuItem::Separator)
-        .add_item(toggle);
+    let mut menu = SystemTrayMenu::new().add_item(title);
+
+    if devices.is_empty() {
+        menu = menu
+            .add_item(CustomMenuItem::new("status".to_string(), "Status: Disconnected").disabled());
+    }
+    for device in devices {
+        let label = format!(
+            "{}: {}",
+            device.device,
+            match device.connection {
+                ConnectionState::Connected => "Connected",
+                ConnectionState::Waiting => "Waiting",
+                ConnectionState::Disconnected => "Disconnected",
+            }
+        );
+        menu = menu
+            .add_item(CustomMenuItem::new(format!("status/{}", device.device), label).disabled());
+    }
+
+    menu.add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(toggle)
+}
 
-    SystemTray::new().with_menu(menu)
+fn generate_tray() -> SystemTray {
+    SystemTray::new().with_menu(generate_tray_menu(Vec::new()))
 }
 
-fn main() {
+#[tokio::main]
+async fn main() {
+    tauri::async_runtime::set(tokio::runtime::Handle::current());
     tauri::Builder::default()
-        .manage(ApplicationState {
-            connection: Mutex::new(ConnectionState::Disconnected),
-            port: Mutex::new(None),
-            rate: Mutex::new(None),
-            config: Mutex::new(AppConfig::default()),
-        })
         .plugin(tauri_plugin_single_instance::init(|app, _, _| {
             app.trigger_global("ratpad://single-instance", None);
         }))
         .setup(|app| {
-            (*app.state::<ApplicationState>().lock_config().unwrap())
-                .set(AppConfig::load_config(app.handle()))
-                .save(app.handle());
-            start_serial_listener(app);
+            let runtime = PadRuntime::new(
+                app.path_resolver()
+                    .app_config_dir()
+                    .expect("Unable to resolve config path"),
+            );
+            app.manage(runtime.clone());
+
             let handle = app.handle();
-            let evt_handle = handle.clone();
-            app.listen_global("ratpad://serial", move |event| {
-                if let Some(payload) = event.payload() {
-                    if let Ok(parsed) = serde_json::from_str::<SerialEvent>(payload) {
-                        evt_handle
-                            .emit_all("ratpad://serial", parsed.clone())
-                            .expect("Serial emit failed");
+            let emit_handle = handle.clone();
+            runtime.set_emitter(move |event, payload| {
+                emit_handle.emit_all(event, payload).expect("Emit failed");
+            });
 
-                        match parsed {
-                            SerialEvent::Connect => evt_handle
+            let tray_handle = handle.clone();
+            let tray_runtime = runtime.clone();
+            runtime.listen("ratpad://serial", move |payload| {
+                if let Some(payload) = payload {
+                    match serde_json::from_str::<SerialEvent>(payload) {
+                        Ok(SerialEvent::Connect { .. }) | Ok(SerialEvent::Disconnect { .. }) => {
+                            tray_handle
                                 .tray_handle()
-                                .get_item("status")
-                                .set_title("Status: Connected")
-                                .unwrap(),
-                            SerialEvent::Disconnect => evt_handle
-                                .tray_handle()
-                                .get_item("status")
-                                .set_title("Status: Disconnected")
-                                .unwrap(),
-                            SerialEvent::Event(_) => (),
+                                .set_menu(generate_tray_menu(tray_runtime.state().devices()))
+                                .unwrap()
                         }
+                        _ => (),
                     }
                 }
             });
@@ -98,18 +108,7 @@ fn main() {
                     .show()
                     .unwrap();
             });
-            if let Some(config) = app.state::<ApplicationState>().lock_config() {
-                if let Some(dev_port) = config.clone().device_port {
-                    if let Some(dev_rate) = config.clone().device_rate {
-                        if let Ok(data) = serde_json::to_string(&ListenerCommand::Connect {
-                            new_port: dev_port.clone(),
-                            new_rate: dev_rate,
-                        }) {
-                            app.trigger_global("ratpad://serial/cmd", Some(data));
-                        }
-                    }
-                }
-            }
+            start_runtime(&runtime);
 
             Ok(())
         })
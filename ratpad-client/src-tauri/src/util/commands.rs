@@ -1,18 +1,29 @@
 pub mod command_handler {
-    use std::{sync::mpsc, time::Duration};
+    use std::{collections::HashMap, iter::once, time::Duration};
 
     use serde::{Deserialize, Serialize};
     use serde_json::json;
-    use tauri::{AppHandle, Manager};
 
     use crate::{
         ratpad_communication::{
             CommandType, EventType, Message, MessageType, ModeConfig, PadConfig,
         },
-        serial_client::{get_ports, send_serial_command, ListenerCommand, PortInfo, SerialEvent},
+        serial_client::{
+            device_id_for_port, get_ports, send_listener_command, send_serial_command,
+            ListenerCommand, PortInfo,
+        },
         util::{
-            app_state::{ApplicationState, ConnectionState},
-            configuration::{AppConfig, AppModeConfig, PadCompat},
+            app_state::DeviceState,
+            config_format::ConfigFormat,
+            config_store::{convert_config, ConfigEvent},
+            config_sync::{compute_diff, push_changes, reconcile, ConfigDiff},
+            configuration::{AppConfig, AppModeConfig, ConfigEdit, SyncPolicy},
+            input_injection::Delivery,
+            keyboard_layout::{KeyboardLayout, TextFallback},
+            keycode_catalog::{complete, parse_chord},
+            pad_runtime::PadRuntime,
+            pad_transport::{parse_address, serial_port},
+            request_router::DEFAULT_REQUEST_TIMEOUT,
         },
     };
 
@@ -36,40 +47,183 @@ pub mod command_handler {
     #[serde(tag = "type")]
     pub enum CommandTypes {
         #[serde(rename = "serial.connect")]
-        SerialConnect { port: String, rate: u32 },
+        SerialConnect {
+            port: String,
+            rate: u32,
+            device: Option<String>,
+        },
 
         #[serde(rename = "serial.disconnect")]
-        SerialDisconnect,
+        SerialDisconnect { device: Option<String> },
 
         #[serde(rename = "serial.list_ports")]
-        SerialListPorts,
+        SerialListPorts { device: Option<String> },
 
         #[serde(rename = "serial.get_state")]
-        SerialGetState,
+        SerialGetState { device: Option<String> },
 
         #[serde(rename = "config.get_config")]
-        ConfGetConfig,
+        ConfGetConfig { device: Option<String> },
+
+        /// The last problem loading or saving the config, such as a recovery at startup.
+        #[serde(rename = "config.last_event")]
+        ConfLastEvent {},
 
         #[serde(rename = "config.set_color")]
-        ConfSetColor { color: SetColorType },
+        ConfSetColor {
+            color: SetColorType,
+            device: Option<String>,
+        },
 
         #[serde(rename = "config.write_mode")]
-        ConfWriteMode { mode: AppModeConfig },
+        ConfWriteMode {
+            mode: AppModeConfig,
+            device: Option<String>,
+        },
 
         #[serde(rename = "config.delete_mode")]
-        ConfDeleteMode { key: String },
+        ConfDeleteMode { key: String, device: Option<String> },
 
         #[serde(rename = "config.clear_modes")]
-        ConfClearModes,
+        ConfClearModes { device: Option<String> },
+
+        /// Reverses the newest color or mode edit, here and on every connected pad.
+        #[serde(rename = "config.undo")]
+        ConfUndo {},
+
+        #[serde(rename = "config.redo")]
+        ConfRedo {},
+
+        #[serde(rename = "config.set_device_modes")]
+        ConfSetDeviceModes {
+            device: String,
+            modes: Option<Vec<String>>,
+        },
 
         #[serde(rename = "pad.set_mode")]
-        PadSetMode { mode: String },
+        PadSetMode {
+            mode: String,
+            device: Option<String>,
+        },
 
         #[serde(rename = "pad.set_home")]
-        PadSetHome,
+        PadSetHome { device: Option<String> },
 
         #[serde(rename = "pad.get_config")]
-        PadGetConfig,
+        PadGetConfig { device: Option<String> },
+
+        #[serde(rename = "config.set_sync_policy")]
+        ConfSetSyncPolicy { policy: SyncPolicy },
+
+        #[serde(rename = "config.set_auto_switch")]
+        ConfSetAutoSwitch { enabled: bool },
+
+        /// Leaves the fallback as it is when `fallback` is missing.
+        #[serde(rename = "config.set_keyboard_layout")]
+        ConfSetKeyboardLayout {
+            layout: KeyboardLayout,
+            fallback: Option<TextFallback>,
+        },
+
+        /// Rewrites the modes on every connected pad whose keys change with the new delivery.
+        #[serde(rename = "config.set_delivery")]
+        ConfSetDelivery { delivery: Delivery },
+
+        /// Rewrites the config file in another format, moving the old one into the backups.
+        #[serde(rename = "config.set_format")]
+        ConfSetFormat { format: ConfigFormat },
+
+        /// Checks a partly typed chord and offers ways to finish its last key, for editors.
+        #[serde(rename = "keys.complete")]
+        KeysComplete { keys: String },
+
+        #[serde(rename = "sync.get_diff")]
+        SyncGetDiff { device: Option<String> },
+
+        #[serde(rename = "sync.apply")]
+        SyncApply {
+            policy: SyncPolicy,
+            device: Option<String>,
+        },
+
+        #[serde(rename = "profile.list")]
+        ProfileList {},
+
+        /// Starts with the active profile's colors and no modes.
+        #[serde(rename = "profile.create")]
+        ProfileCreate { name: String },
+
+        #[serde(rename = "profile.clone")]
+        ProfileClone { source: String, name: String },
+
+        #[serde(rename = "profile.rename")]
+        ProfileRename { name: String, new_name: String },
+
+        #[serde(rename = "profile.delete")]
+        ProfileDelete { name: String },
+
+        /// Brings every connected pad in line with the profile, sending only what differs.
+        #[serde(rename = "profile.activate")]
+        ProfileActivate { name: String },
+    }
+
+    impl CommandTypes {
+        /// The pad command this request ends up sending, if any.
+        pub fn required_capability(&self) -> Option<CommandType> {
+            match self {
+                CommandTypes::ConfSetColor { .. } => Some(CommandType::SetColor),
+                CommandTypes::ConfWriteMode { .. } => Some(CommandType::WriteMode),
+                CommandTypes::ConfDeleteMode { .. } => Some(CommandType::DeleteMode),
+                CommandTypes::ConfClearModes { .. } => Some(CommandType::ClearModes),
+                CommandTypes::ConfUndo {} | CommandTypes::ConfRedo {} => {
+                    Some(CommandType::WriteMode)
+                }
+                CommandTypes::PadSetMode { .. } => Some(CommandType::SetMode),
+                CommandTypes::PadSetHome { .. } => Some(CommandType::SetHome),
+                CommandTypes::PadGetConfig { .. } => Some(CommandType::ReadConfig),
+                CommandTypes::SyncGetDiff { .. } => Some(CommandType::ReadConfig),
+                CommandTypes::SyncApply { .. } => Some(CommandType::ReadConfig),
+                CommandTypes::ProfileActivate { .. } => Some(CommandType::ReadConfig),
+                _ => None,
+            }
+        }
+
+        /// The device this request targets. `None` means every connected device, or the first one
+        /// for requests that expect a reply.
+        pub fn device(&self) -> Option<String> {
+            match self {
+                CommandTypes::SerialConnect { device, .. }
+                | CommandTypes::SerialDisconnect { device }
+                | CommandTypes::SerialListPorts { device }
+                | CommandTypes::SerialGetState { device }
+                | CommandTypes::ConfGetConfig { device }
+                | CommandTypes::ConfSetColor { device, .. }
+                | CommandTypes::ConfWriteMode { device, .. }
+                | CommandTypes::ConfDeleteMode { device, .. }
+                | CommandTypes::ConfClearModes { device }
+                | CommandTypes::PadSetMode { device, .. }
+                | CommandTypes::PadSetHome { device }
+                | CommandTypes::PadGetConfig { device }
+                | CommandTypes::SyncGetDiff { device }
+                | CommandTypes::SyncApply { device, .. } => device.clone(),
+                CommandTypes::ConfSetDeviceModes { device, .. } => Some(device.clone()),
+                CommandTypes::ConfSetSyncPolicy { .. }
+                | CommandTypes::ConfSetAutoSwitch { .. }
+                | CommandTypes::ConfSetKeyboardLayout { .. }
+                | CommandTypes::ConfSetDelivery { .. }
+                | CommandTypes::ConfSetFormat { .. }
+                | CommandTypes::ConfUndo {}
+                | CommandTypes::ConfRedo {}
+                | CommandTypes::KeysComplete { .. }
+                | CommandTypes::ConfLastEvent {}
+                | CommandTypes::ProfileList {}
+                | CommandTypes::ProfileCreate { .. }
+                | CommandTypes::ProfileClone { .. }
+                | CommandTypes::ProfileRename { .. }
+                | CommandTypes::ProfileDelete { .. }
+                | CommandTypes::ProfileActivate { .. } => None,
+            }
+        }
     }
 
     #[derive(Serialize, Deserialize, Clone)]
@@ -85,15 +239,14 @@ pub mod command_handler {
         SerialListPorts { result: Vec<PortInfo> },
 
         #[serde(rename = "serial.get_state")]
-        SerialGetState {
-            connected: ConnectionState,
-            port: Option<String>,
-            rate: Option<u32>,
-        },
+        SerialGetState { devices: Vec<DeviceState> },
 
         #[serde(rename = "config.get_config")]
         ConfGetConfig { config: AppConfig },
 
+        #[serde(rename = "config.last_event")]
+        ConfLastEvent { event: Option<ConfigEvent> },
+
         #[serde(rename = "config.set_color")]
         ConfSetColor {},
 
@@ -106,6 +259,22 @@ pub mod command_handler {
         #[serde(rename = "config.clear_modes")]
         ConfClearModes {},
 
+        /// `command` is the one whose change was undone, `diffs` what each connected pad was sent.
+        #[serde(rename = "config.undo")]
+        ConfUndo {
+            command: String,
+            diffs: HashMap<String, ConfigDiff>,
+        },
+
+        #[serde(rename = "config.redo")]
+        ConfRedo {
+            command: String,
+            diffs: HashMap<String, ConfigDiff>,
+        },
+
+        #[serde(rename = "config.set_device_modes")]
+        ConfSetDeviceModes {},
+
         #[serde(rename = "pad.set_mode")]
         PadSetMode {},
 
@@ -114,200 +283,620 @@ pub mod command_handler {
 
         #[serde(rename = "pad.get_config")]
         PadGetConfig { config: PadConfig },
+
+        #[serde(rename = "config.set_sync_policy")]
+        ConfSetSyncPolicy {},
+
+        #[serde(rename = "config.set_auto_switch")]
+        ConfSetAutoSwitch {},
+
+        #[serde(rename = "config.set_keyboard_layout")]
+        ConfSetKeyboardLayout {},
+
+        #[serde(rename = "config.set_delivery")]
+        ConfSetDelivery {},
+
+        /// `path` is the config file now in use.
+        #[serde(rename = "config.set_format")]
+        ConfSetFormat { path: String },
+
+        /// `chord` is the canonical form when `keys` parses, `error` says why when it doesn't.
+        #[serde(rename = "keys.complete")]
+        KeysComplete {
+            chord: Option<String>,
+            error: Option<String>,
+            completions: Vec<String>,
+        },
+
+        #[serde(rename = "sync.get_diff")]
+        SyncGetDiff { diff: ConfigDiff },
+
+        #[serde(rename = "sync.apply")]
+        SyncApply { diff: ConfigDiff },
+
+        #[serde(rename = "profile.list")]
+        ProfileList {
+            active: String,
+            profiles: Vec<String>,
+        },
+
+        #[serde(rename = "profile.create")]
+        ProfileCreate {},
+
+        #[serde(rename = "profile.clone")]
+        ProfileClone {},
+
+        #[serde(rename = "profile.rename")]
+        ProfileRename {},
+
+        #[serde(rename = "profile.delete")]
+        ProfileDelete {},
+
+        /// What was pushed to each connected device, or why it couldn't be.
+        #[serde(rename = "profile.activate")]
+        ProfileActivate {
+            diffs: HashMap<String, Result<ConfigDiff, String>>,
+        },
     }
 
-    fn publish_and_wait(
-        app: AppHandle,
+    pub fn publish_and_wait(
+        app: PadRuntime,
+        device: Option<String>,
         command: Message,
         message: MessageType,
         timeout: Option<Duration>,
     ) -> Result<Message, String> {
-        let (tx, rx) = mpsc::channel::<Message>();
-        let listener = app.listen_global("ratpad://serial", move |evt| {
-            if let Some(payload) = evt.payload() {
-                if let Ok(parsed) = serde_json::from_str::<SerialEvent>(payload) {
-                    match parsed {
-                        SerialEvent::Event(event) => {
-                            if event.message_type == message {
-                                tx.send(event).expect("Failed to send over mpsc");
-                            }
-                        }
-                        _ => (),
-                    }
-                }
+        let device = app
+            .state()
+            .resolve_device(device)
+            .ok_or("No device connected".to_string())?;
+        let timeout = timeout.unwrap_or(DEFAULT_REQUEST_TIMEOUT);
+        // Legacy firmware reads `name#id` as an unknown command, so it is sent plain names.
+        let tagged = app
+            .state()
+            .capabilities(&device)
+            .is_none_or(|c| c.protocol > 0);
+        let (id, rx) = app
+            .state()
+            .lock_requests()
+            .ok_or("Failed to lock request table".to_string())?
+            .register(device.clone(), message, tagged, timeout);
+
+        send_serial_command(
+            app.clone(),
+            Some(device),
+            Message {
+                id: tagged.then_some(id),
+                ..command
+            },
+        );
+
+        let result = rx.recv_timeout(timeout).or(Err("Timed out".to_string()));
+        if result.is_err() {
+            if let Some(mut requests) = app.state().lock_requests() {
+                requests.cancel(id);
             }
-        });
+        }
+        result
+    }
 
-        send_serial_command(app.clone(), command);
+    pub fn fetch_pad_config(
+        app: PadRuntime,
+        device: Option<String>,
+    ) -> Result<PadConfig, &'static str> {
+        publish_and_wait(
+            app,
+            device,
+            Message {
+                message_type: MessageType::Command(CommandType::ReadConfig),
+                data: None,
+                id: None,
+            },
+            MessageType::Event(EventType::Config),
+            None,
+        )
+        .or(Err("Failed to retrieve config"))
+        .and_then(|res| {
+            res.data
+                .ok_or(())
+                .or(Err("Config not returned by pad."))
+                .and_then(|data| {
+                    serde_json::from_value::<PadConfig>(data).or(Err("Config failed to parse"))
+                })
+        })
+    }
 
-        let result: Result<Message, String>;
+    /// Sends to the target device, or to every connected device whose mode set includes `mode`.
+    fn send_for_mode(app: PadRuntime, device: Option<String>, mode: &str, message: Message) {
+        if device.is_some() {
+            send_serial_command(app, device, message);
+            return;
+        }
 
-        if let Some(to) = timeout {
-            if let Ok(recv) = rx.recv_timeout(to) {
-                result = Ok(recv);
-            } else {
-                result = Err("Timed out".to_string());
-            }
-        } else {
-            if let Ok(recv) = rx.recv() {
-                result = Ok(recv);
-            } else {
-                result = Err("RECV failed".to_string());
-            }
+        let state = app.state();
+        let targets: Vec<String> = match state.lock_config() {
+            Some(config) => state
+                .connected_devices()
+                .into_iter()
+                .filter(|d| config.device_allows_mode(d, mode))
+                .collect(),
+            None => Vec::new(),
+        };
+        for target in targets {
+            send_serial_command(app.clone(), Some(target), message.clone());
         }
+    }
+
+    /// Makes `edits`, saves, and records how to reverse them for `config.undo`.
+    fn apply_edits(
+        app: &PadRuntime,
+        config: &mut AppConfig,
+        command: &str,
+        edits: Vec<ConfigEdit>,
+    ) {
+        let undo = config.apply_all(edits);
+        config.save(app.clone());
+        if let Some(mut journal) = app.state().lock_journal() {
+            journal.record(command, undo);
+        }
+    }
 
-        app.unlisten(listener);
-        return result;
+    /// Undoes or redoes the newest change, then sends the pads what that changed for them.
+    fn step_history(
+        app: PadRuntime,
+        undo: bool,
+    ) -> Result<(String, HashMap<String, ConfigDiff>), &'static str> {
+        let (command, previous, current) = {
+            let state = app.state();
+            let mut config = state.lock_config().ok_or("Failed to lock state")?;
+            let mut journal = state.lock_journal().ok_or("Failed to lock history")?;
+            let previous = config.clone();
+            let command = match undo {
+                true => journal.undo(&mut config).ok_or("Nothing to undo")?,
+                false => journal.redo(&mut config).ok_or("Nothing to redo")?,
+            };
+            (command, previous, config.save(app.clone()))
+        };
+        Ok((command, push_changes(app, &previous, &current)))
     }
 
-    pub fn execute(
-        app: AppHandle,
-        command: CommandTypes,
-    ) -> Result<CommandReturnTypes, &'static str> {
+    pub fn execute(app: PadRuntime, command: CommandTypes) -> Result<CommandReturnTypes, String> {
+        if let Some(required) = command.required_capability() {
+            let state = app.state();
+            let targets = match command.device() {
+                Some(device) => vec![device],
+                None => state.connected_devices(),
+            };
+            for target in targets {
+                if let Some(capabilities) = state.capabilities(&target) {
+                    if !capabilities.supports(&required) {
+                        return Err(
+                            "Command not supported by the connected pad's firmware".to_string()
+                        );
+                    }
+                }
+            }
+        }
+
         match command {
-            CommandTypes::SerialConnect { port, rate } => {
-                serde_json::to_string(&ListenerCommand::Connect {
-                    new_port: port,
-                    new_rate: rate,
-                })
-                .or(Err("Command serialization failed"))
-                .and_then(|com| {
-                    app.trigger_global("ratpad://serial", Some(com));
-                    Ok(CommandReturnTypes::SerialConnect {})
-                })
+            CommandTypes::SerialConnect { port, rate, device } => {
+                parse_address(&port, rate).or(Err("Invalid pad address"))?;
+                let device = device.unwrap_or_else(|| match serial_port(&port) {
+                    Some(serial) => device_id_for_port(&serial),
+                    None => port.clone(),
+                });
+                send_listener_command(
+                    app.clone(),
+                    ListenerCommand::Connect {
+                        device,
+                        new_port: port,
+                        new_rate: rate,
+                    },
+                );
+                Ok(CommandReturnTypes::SerialConnect {})
+            }
+            CommandTypes::SerialDisconnect { device } => {
+                let targets = match device {
+                    Some(device) => vec![device],
+                    None => app
+                        .state()
+                        .devices()
+                        .into_iter()
+                        .map(|d| d.device)
+                        .collect(),
+                };
+                for target in targets {
+                    send_listener_command(
+                        app.clone(),
+                        ListenerCommand::Disconnect { device: target },
+                    );
+                }
+                Ok(CommandReturnTypes::SerialDisconnect {})
             }
-            CommandTypes::SerialDisconnect => serde_json::to_string(&ListenerCommand::Disconnect)
-                .or(Err("Command serialization failed"))
-                .and_then(|com| {
-                    app.trigger_global("ratpad://serial", Some(com));
-                    Ok(CommandReturnTypes::SerialDisconnect {})
+            CommandTypes::SerialListPorts { device } => get_ports()
+                .or(Err("Port list failure".to_string()))
+                .map(|res| CommandReturnTypes::SerialListPorts {
+                    result: res
+                        .into_iter()
+                        .filter(|p| device.is_none() || p.device_id() == device)
+                        .collect(),
                 }),
-            CommandTypes::SerialListPorts => get_ports()
-                .or(Err("Port list failure"))
-                .and_then(|res| Ok(CommandReturnTypes::SerialListPorts { result: res })),
-            CommandTypes::SerialGetState => Ok(CommandReturnTypes::SerialGetState {
-                connected: app
-                    .state::<ApplicationState>()
-                    .connection
-                    .lock()
-                    .unwrap()
-                    .clone(),
-                port: app.state::<ApplicationState>().port.lock().unwrap().clone(),
-                rate: *app.state::<ApplicationState>().rate.lock().unwrap(),
+            CommandTypes::SerialGetState { device } => Ok(CommandReturnTypes::SerialGetState {
+                devices: app
+                    .state()
+                    .devices()
+                    .into_iter()
+                    .filter(|d| device.is_none() || Some(d.device.clone()) == device)
+                    .collect(),
             }),
-            CommandTypes::PadGetConfig => publish_and_wait(
-                app.clone(),
-                Message {
-                    message_type: MessageType::Command(CommandType::ReadConfig),
-                    data: None,
-                },
-                MessageType::Event(EventType::Config),
-                None,
-            )
-            .or(Err("Failed to retrieve config"))
-            .and_then(|res| {
-                res.data
-                    .ok_or(())
-                    .or(Err("Config not returned by pad."))
-                    .and_then(|data| {
-                        serde_json::from_value::<PadConfig>(data)
-                            .or(Err("Config failed to parse"))
-                            .and_then(|p| Ok(CommandReturnTypes::PadGetConfig { config: p }))
-                    })
+            CommandTypes::PadGetConfig { device } => Ok(CommandReturnTypes::PadGetConfig {
+                config: fetch_pad_config(app.clone(), device)?,
             }),
-            CommandTypes::ConfSetColor { color } => {
+            CommandTypes::ConfSetColor { color, device } => {
                 if let Ok(parsed) = serde_json::to_value::<SetColorType>(color.clone()) {
-                    if let Some(mut state) = app.clone().state::<ApplicationState>().lock_config() {
-                        state.set_color(color.clone()).save(app.clone());
+                    if let Some(mut state) = app.clone().state().lock_config() {
+                        let edit = ConfigEdit::SetColor {
+                            color: color.clone(),
+                        };
+                        apply_edits(&app, &mut state, "config.set_color", vec![edit]);
                     } else {
-                        return Err("Failed to lock state");
+                        return Err("Failed to lock state".to_string());
                     }
                     send_serial_command(
                         app.clone(),
+                        device,
                         Message {
                             message_type: MessageType::Command(CommandType::SetColor),
                             data: Some(parsed),
+                            id: None,
                         },
                     );
                     Ok(CommandReturnTypes::ConfSetColor {})
                 } else {
-                    Err("Failed to parse color data")
+                    Err("Failed to parse color data".to_string())
                 }
             }
-            CommandTypes::ConfWriteMode { mode } => {
-                if let Ok(parsed) = serde_json::to_value::<ModeConfig>(mode.clone().to_pad()) {
-                    if let Some(mut state) = app.clone().state::<ApplicationState>().lock_config() {
-                        state.write_mode(mode).save(app.clone());
+            CommandTypes::ConfWriteMode { mode, device } => {
+                mode.check_chords()
+                    .and_then(|_| mode.check_focus())
+                    .map_err(|reason| format!("Mode {}: {reason}", mode.key()))?;
+                let key = mode.key();
+                // Modes inheriting from this one change with it, so they are rewritten too.
+                let written: Vec<ModeConfig> =
+                    if let Some(mut state) = app.clone().state().lock_config() {
+                        state.check_parent(&mode)?;
+                        let mut edits = vec![ConfigEdit::WriteMode {
+                            mode: Box::new(mode),
+                        }];
+                        if let Some(target) = device.clone() {
+                            edits.push(ConfigEdit::IncludeMode {
+                                device: target,
+                                key: key.clone(),
+                            });
+                        }
+                        apply_edits(&app, &mut state, "config.write_mode", edits);
+
+                        once(key.clone())
+                            .chain(state.descendants(&key))
+                            .filter_map(|k| state.mode_to_pad(&k))
+                            .collect()
                     } else {
-                        return Err("Failed to lock state");
-                    }
-                    send_serial_command(
+                        return Err("Failed to lock state".to_string());
+                    };
+
+                for pad_mode in written {
+                    let parsed = serde_json::to_value::<ModeConfig>(pad_mode.clone())
+                        .or(Err("Failed to parse mode data"))?;
+                    // Only the mode itself goes to the requested device; descendants go wherever
+                    // they are in use.
+                    let target = if pad_mode.key == key {
+                        device.clone()
+                    } else {
+                        None
+                    };
+                    send_for_mode(
                         app.clone(),
+                        target,
+                        &pad_mode.key,
                         Message {
                             message_type: MessageType::Command(CommandType::WriteMode),
                             data: Some(parsed),
+                            id: None,
                         },
                     );
-                    Ok(CommandReturnTypes::ConfWriteMode {})
-                } else {
-                    Err("Failed to parse mode data")
                 }
+                Ok(CommandReturnTypes::ConfWriteMode {})
             }
-            CommandTypes::ConfDeleteMode { key } => {
-                if let Some(mut state) = app.clone().state::<ApplicationState>().lock_config() {
-                    state.delete_mode(key.clone()).save(app.clone());
+            CommandTypes::ConfDeleteMode { key, device } => {
+                // A delete aimed at one device only drops the mode from that device's mode set, so
+                // the other pads keep it.
+                if let Some(mut state) = app.clone().state().lock_config() {
+                    let edit = match device.clone() {
+                        Some(d) => ConfigEdit::ExcludeMode {
+                            device: d,
+                            key: key.clone(),
+                        },
+                        None => {
+                            if !state.descendants(&key).is_empty() {
+                                return Err("Other modes inherit from this mode".to_string());
+                            }
+                            ConfigEdit::DeleteMode { key: key.clone() }
+                        }
+                    };
+                    apply_edits(&app, &mut state, "config.delete_mode", vec![edit]);
                 } else {
-                    return Err("Failed to lock state");
+                    return Err("Failed to lock state".to_string());
                 }
                 send_serial_command(
                     app.clone(),
+                    device,
                     Message {
                         message_type: MessageType::Command(CommandType::DeleteMode),
                         data: Some(json!({"key": key})),
+                        id: None,
                     },
                 );
                 Ok(CommandReturnTypes::ConfDeleteMode {})
             }
-            CommandTypes::ConfClearModes => {
-                if let Some(mut state) = app.clone().state::<ApplicationState>().lock_config() {
-                    state.clear_modes().save(app.clone());
+            CommandTypes::ConfClearModes { device } => {
+                if let Some(mut state) = app.clone().state().lock_config() {
+                    let edit = match device.clone() {
+                        Some(d) => ConfigEdit::SetDeviceModes {
+                            device: d,
+                            modes: Some(Vec::new()),
+                        },
+                        None => ConfigEdit::ClearModes {},
+                    };
+                    apply_edits(&app, &mut state, "config.clear_modes", vec![edit]);
                 } else {
-                    return Err("Failed to lock state");
+                    return Err("Failed to lock state".to_string());
                 }
                 send_serial_command(
                     app.clone(),
+                    device,
                     Message {
                         message_type: MessageType::Command(CommandType::ClearModes),
                         data: None,
+                        id: None,
                     },
                 );
                 Ok(CommandReturnTypes::ConfClearModes {})
             }
-            CommandTypes::PadSetHome => {
+            CommandTypes::ConfUndo {} => {
+                let (command, diffs) = step_history(app.clone(), true)?;
+                Ok(CommandReturnTypes::ConfUndo { command, diffs })
+            }
+            CommandTypes::ConfRedo {} => {
+                let (command, diffs) = step_history(app.clone(), false)?;
+                Ok(CommandReturnTypes::ConfRedo { command, diffs })
+            }
+            CommandTypes::ConfSetDeviceModes { device, modes } => {
+                if let Some(mut state) = app.clone().state().lock_config() {
+                    state.set_device_modes(device, modes).save(app.clone());
+                    Ok(CommandReturnTypes::ConfSetDeviceModes {})
+                } else {
+                    Err("Failed to lock state".to_string())
+                }
+            }
+            CommandTypes::PadSetHome { device } => {
                 send_serial_command(
                     app.clone(),
+                    device,
                     Message {
                         message_type: MessageType::Command(CommandType::SetHome),
                         data: None,
+                        id: None,
                     },
                 );
                 Ok(CommandReturnTypes::PadSetHome {})
             }
-            CommandTypes::PadSetMode { mode } => {
-                send_serial_command(
+            CommandTypes::PadSetMode { mode, device } => {
+                send_for_mode(
                     app.clone(),
+                    device,
+                    &mode,
                     Message {
                         message_type: MessageType::Command(CommandType::SetMode),
                         data: Some(json!({"mode": mode})),
+                        id: None,
                     },
                 );
                 Ok(CommandReturnTypes::PadSetMode {})
             }
-            CommandTypes::ConfGetConfig => {
+            CommandTypes::ConfGetConfig { device } => {
+                let config = app
+                    .state()
+                    .lock_config()
+                    .ok_or("Failed to lock state")?
+                    .clone();
                 Ok(CommandReturnTypes::ConfGetConfig {
-                    config: AppConfig::load_config(app.clone()),
+                    config: match device {
+                        Some(d) => config.device_view(&d),
+                        None => config,
+                    },
                 })
             }
+            CommandTypes::ConfLastEvent {} => match app.state().config_event.lock() {
+                Ok(event) => Ok(CommandReturnTypes::ConfLastEvent {
+                    event: event.clone(),
+                }),
+                Err(_) => Err("Failed to lock state".to_string()),
+            },
+            CommandTypes::ConfSetSyncPolicy { policy } => {
+                if let Some(mut state) = app.clone().state().lock_config() {
+                    state.set_sync_policy(policy).save(app.clone());
+                    Ok(CommandReturnTypes::ConfSetSyncPolicy {})
+                } else {
+                    Err("Failed to lock state".to_string())
+                }
+            }
+            CommandTypes::ConfSetAutoSwitch { enabled } => {
+                if let Some(mut state) = app.clone().state().lock_config() {
+                    state.set_auto_switch(enabled).save(app.clone());
+                    Ok(CommandReturnTypes::ConfSetAutoSwitch {})
+                } else {
+                    Err("Failed to lock state".to_string())
+                }
+            }
+            CommandTypes::ConfSetKeyboardLayout { layout, fallback } => {
+                if let Some(mut state) = app.clone().state().lock_config() {
+                    state
+                        .set_keyboard_layout(layout, fallback)
+                        .save(app.clone());
+                    Ok(CommandReturnTypes::ConfSetKeyboardLayout {})
+                } else {
+                    Err("Failed to lock state".to_string())
+                }
+            }
+            CommandTypes::ConfSetDelivery { delivery } => {
+                let changed: Vec<ModeConfig> =
+                    if let Some(mut state) = app.clone().state().lock_config() {
+                        let previous = state.delivery;
+                        state.set_delivery(delivery).save(app.clone());
+                        state
+                            .modes
+                            .iter()
+                            .map(|m| state.flatten(m))
+                            .map(|m| (m.to_pad_for(previous), m.to_pad_for(delivery)))
+                            .filter(|(before, after)| before != after)
+                            .map(|(_, after)| after)
+                            .collect()
+                    } else {
+                        return Err("Failed to lock state".to_string());
+                    };
+
+                for mode in changed {
+                    if let Ok(parsed) = serde_json::to_value::<ModeConfig>(mode.clone()) {
+                        send_for_mode(
+                            app.clone(),
+                            None,
+                            &mode.key,
+                            Message {
+                                message_type: MessageType::Command(CommandType::WriteMode),
+                                data: Some(parsed),
+                                id: None,
+                            },
+                        );
+                    }
+                }
+                Ok(CommandReturnTypes::ConfSetDelivery {})
+            }
+            CommandTypes::ConfSetFormat { format } => {
+                if let Some(state) = app.clone().state().lock_config() {
+                    match convert_config(&app.config_dir(), &state, format) {
+                        Ok(path) => Ok(CommandReturnTypes::ConfSetFormat {
+                            path: path.display().to_string(),
+                        }),
+                        Err(reason) => Err(format!("Failed to convert the config file: {reason}")),
+                    }
+                } else {
+                    Err("Failed to lock state".to_string())
+                }
+            }
+            CommandTypes::KeysComplete { keys } => {
+                let (chord, error) = match parse_chord(&keys) {
+                    Ok(chord) => (Some(chord.to_string()), None),
+                    Err(error) => (None, Some(error)),
+                };
+                Ok(CommandReturnTypes::KeysComplete {
+                    chord,
+                    error,
+                    completions: complete(&keys),
+                })
+            }
+            CommandTypes::SyncGetDiff { device } => {
+                let device = app
+                    .state()
+                    .resolve_device(device)
+                    .ok_or("No device connected")?;
+                let pad = fetch_pad_config(app.clone(), Some(device.clone()))?;
+                if let Some(state) = app.clone().state().lock_config() {
+                    Ok(CommandReturnTypes::SyncGetDiff {
+                        diff: compute_diff(&state.device_view(&device), &pad),
+                    })
+                } else {
+                    Err("Failed to lock state".to_string())
+                }
+            }
+            CommandTypes::SyncApply { policy, device } => {
+                if policy == SyncPolicy::Prompt {
+                    return Err("Sync must be applied with client_wins or pad_wins".to_string());
+                }
+                let device = app
+                    .state()
+                    .resolve_device(device)
+                    .ok_or("No device connected")?;
+                Ok(CommandReturnTypes::SyncApply {
+                    diff: reconcile(app.clone(), device, Some(policy))?,
+                })
+            }
+            CommandTypes::ProfileList {} => {
+                if let Some(state) = app.clone().state().lock_config() {
+                    Ok(CommandReturnTypes::ProfileList {
+                        active: state.profile.clone(),
+                        profiles: state.profile_names(),
+                    })
+                } else {
+                    Err("Failed to lock state".to_string())
+                }
+            }
+            CommandTypes::ProfileCreate { name } => {
+                if let Some(mut state) = app.clone().state().lock_config() {
+                    state.create_profile(name)?.save(app.clone());
+                    Ok(CommandReturnTypes::ProfileCreate {})
+                } else {
+                    Err("Failed to lock state".to_string())
+                }
+            }
+            CommandTypes::ProfileClone { source, name } => {
+                if let Some(mut state) = app.clone().state().lock_config() {
+                    state.clone_profile(source, name)?.save(app.clone());
+                    Ok(CommandReturnTypes::ProfileClone {})
+                } else {
+                    Err("Failed to lock state".to_string())
+                }
+            }
+            CommandTypes::ProfileRename { name, new_name } => {
+                if let Some(mut state) = app.clone().state().lock_config() {
+                    state.rename_profile(name, new_name)?.save(app.clone());
+                    Ok(CommandReturnTypes::ProfileRename {})
+                } else {
+                    Err("Failed to lock state".to_string())
+                }
+            }
+            CommandTypes::ProfileDelete { name } => {
+                if let Some(mut state) = app.clone().state().lock_config() {
+                    state.delete_profile(name)?.save(app.clone());
+                    Ok(CommandReturnTypes::ProfileDelete {})
+                } else {
+                    Err("Failed to lock state".to_string())
+                }
+            }
+            CommandTypes::ProfileActivate { name } => {
+                if let Some(mut state) = app.clone().state().lock_config() {
+                    state.activate_profile(name)?.save(app.clone());
+                    // The history belongs to the profile that was active.
+                    if let Some(mut journal) = app.state().lock_journal() {
+                        journal.clear();
+                    }
+                } else {
+                    return Err("Failed to lock state".to_string());
+                }
+
+                // A pad that can't be reached is reported without holding up the others.
+                let diffs = app
+                    .state()
+                    .connected_devices()
+                    .into_iter()
+                    .map(|device| {
+                        let diff =
+                            reconcile(app.clone(), device.clone(), Some(SyncPolicy::ClientWins))
+                                .map_err(|reason| reason.to_string());
+                        (device, diff)
+                    })
+                    .collect();
+                Ok(CommandReturnTypes::ProfileActivate { diffs })
+            }
         }
     }
 }
@@ -1,48 +1,167 @@
 pub mod configuration {
-    use std::{fs::{create_dir_all, read_to_string, File}, io::Write};
+    use std::{
+        cmp::Reverse,
+        collections::{BTreeMap, HashMap, HashSet},
+        mem::replace,
+        path::PathBuf,
+        sync::OnceLock,
+    };
 
+    use regex::Regex;
     use serde::{Deserialize, Serialize};
-    use serde_json::Error;
-    use tauri::AppHandle;
+    use serde_json::{json, Error, Map, Value};
 
-    use crate::{ratpad_communication::{ColorsConfig, ModeConfig, ModeKey, PadConfig}, util::command_handler::SetColorType};
+    use crate::{
+        ratpad_communication::{ColorsConfig, ModeConfig, ModeKey, PadConfig},
+        serial_client::DeviceIdentity,
+        util::{
+            command_handler::SetColorType,
+            config_store::{self, ConfigEvent},
+            focus_watcher::FocusedWindow,
+            input_injection::{Delivery, MouseButton},
+            keyboard_layout::{KeyboardLayout, TextFallback},
+            keycode_catalog::{canonical, parse_chord},
+            pad_runtime::PadRuntime,
+        },
+    };
 
     pub type Color = (u32, u32, u32);
 
     pub trait PadCompat<I, O> {
         fn to_pad(&self) -> O;
-        fn from_pad(value: O) -> I; 
+        fn from_pad(value: O) -> I;
     }
 
     #[derive(Serialize, Deserialize, Clone, Debug)]
     #[serde(tag = "type")]
     pub enum KeyAction {
         #[serde(rename = "none")]
-        None{},
+        None {},
 
         #[serde(rename = "keypress")]
-        KeyPress{key: String},
+        KeyPress {
+            key: String,
+            #[serde(default)]
+            delivery: Option<Delivery>,
+        },
 
         #[serde(rename = "command")]
-        Command{
+        Command {
             execute: String,
-            args: Option<Vec<String>>
+            args: Option<Vec<String>>,
+            cwd: Option<String>,
+            env: Option<HashMap<String, String>>,
+            timeout: Option<u64>,
+        },
+
+        /// Steps run in order by the client. Pressing the key again while it runs cancels it.
+        #[serde(rename = "macro")]
+        Macro {
+            steps: Vec<MacroStep>,
+            #[serde(default)]
+            delivery: Option<Delivery>,
+        },
+
+        /// Text typed on the pad, translated for the host's keyboard layout.
+        #[serde(rename = "type_text")]
+        TypeText {
+            text: String,
+            #[serde(default)]
+            delivery: Option<Delivery>,
+        },
+    }
+
+    impl KeyAction {
+        /// How this action's input is delivered. `None` defers to the config's `delivery`.
+        pub fn delivery(&self) -> Option<Delivery> {
+            match self {
+                KeyAction::KeyPress { delivery, .. }
+                | KeyAction::Macro { delivery, .. }
+                | KeyAction::TypeText { delivery, .. } => *delivery,
+                _ => None,
+            }
+        }
+
+        /// The chord the pad should type itself when the key is pressed. Chords delivered through
+        /// uinput are left off so they aren't typed twice.
+        pub fn to_pad_for(&self, default: Delivery) -> Option<String> {
+            match self {
+                KeyAction::KeyPress { key, delivery }
+                    if delivery.unwrap_or(default) == Delivery::PadHid =>
+                {
+                    Some(canonical(key))
+                }
+                _ => None,
+            }
+        }
+
+        /// Every chord this action presses, including those inside macros.
+        pub fn chords(&self) -> Vec<&str> {
+            match self {
+                KeyAction::KeyPress { key, .. } => vec![key.as_str()],
+                KeyAction::Macro { steps, .. } => steps
+                    .iter()
+                    .flat_map(|step| match step {
+                        MacroStep::Chord { keys }
+                        | MacroStep::KeyDown { keys }
+                        | MacroStep::KeyUp { keys } => vec![keys.as_str()],
+                        MacroStep::Action { action } => action.chords(),
+                        _ => Vec::new(),
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            }
         }
     }
 
+    /// One step of a [`KeyAction::Macro`]. Keys are chord strings like `KeyPress` uses.
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    #[serde(tag = "type")]
+    pub enum MacroStep {
+        /// Press and release a chord.
+        #[serde(rename = "chord")]
+        Chord { keys: String },
+
+        /// Hold keys down until a matching `key_up` or the end of the macro.
+        #[serde(rename = "key_down")]
+        KeyDown { keys: String },
+
+        #[serde(rename = "key_up")]
+        KeyUp { keys: String },
+
+        #[serde(rename = "text")]
+        Text { text: String },
+
+        #[serde(rename = "delay")]
+        Delay { ms: u64 },
+
+        #[serde(rename = "mouse_move")]
+        MouseMove { x: i32, y: i32 },
+
+        #[serde(rename = "scroll")]
+        Scroll { amount: i32 },
+
+        #[serde(rename = "click")]
+        Click { button: MouseButton },
+
+        /// Run another action and wait for it to finish.
+        #[serde(rename = "action")]
+        Action { action: Box<KeyAction> },
+    }
+
     impl PadCompat<KeyAction, Option<String>> for KeyAction {
         fn to_pad(&self) -> Option<String> {
-            match self {
-                KeyAction::KeyPress{key} => Some(key.clone()),
-                _ => None
-            }
+            self.to_pad_for(Delivery::PadHid)
         }
 
         fn from_pad(value: Option<String>) -> KeyAction {
             if let Some(val) = value {
-                KeyAction::KeyPress{key: val}
+                KeyAction::KeyPress {
+                    key: val,
+                    delivery: None,
+                }
             } else {
-                KeyAction::None{}
+                KeyAction::None {}
             }
         }
     }
@@ -51,52 +170,226 @@ pub mod configuration {
     pub struct KeyConfig {
         label: String,
         action: KeyAction,
-        color: Option<Color>
+        color: Option<Color>,
     }
 
-    impl PadCompat<KeyConfig, ModeKey> for KeyConfig {
-        fn to_pad(&self) -> ModeKey {
+    impl KeyConfig {
+        /// A key with no label that does nothing. In a mode with a parent it blanks the parent's
+        /// key rather than inheriting it, and the pad gets an empty slot.
+        fn blank() -> KeyConfig {
+            KeyConfig {
+                label: String::new(),
+                action: KeyAction::None {},
+                color: None,
+            }
+        }
+
+        fn is_blank(&self) -> bool {
+            self.label.is_empty()
+                && self.color.is_none()
+                && matches!(self.action, KeyAction::None {})
+        }
+
+        pub fn to_pad_for(&self, delivery: Delivery) -> ModeKey {
             ModeKey {
                 label: self.label.clone(),
-                keys: self.action.to_pad(),
-                color: self.color
+                keys: self.action.to_pad_for(delivery),
+                color: self.color,
             }
         }
+    }
+
+    impl PadCompat<KeyConfig, ModeKey> for KeyConfig {
+        fn to_pad(&self) -> ModeKey {
+            self.to_pad_for(Delivery::PadHid)
+        }
 
         fn from_pad(value: ModeKey) -> KeyConfig {
             KeyConfig {
                 label: value.label,
                 action: KeyAction::from_pad(value.keys),
-                color: value.color
+                color: value.color,
             }
         }
     }
 
+    /// The ways the encoder can be used while a mode is active.
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub enum EncoderInput {
+        #[serde(rename = "clockwise")]
+        Clockwise,
+
+        #[serde(rename = "counter_clockwise")]
+        CounterClockwise,
+
+        #[serde(rename = "press")]
+        Press,
+
+        #[serde(rename = "held_clockwise")]
+        HeldClockwise,
+
+        #[serde(rename = "held_counter_clockwise")]
+        HeldCounterClockwise,
+    }
+
+    /// Encoder bindings for a mode. These only live on the host; the pad just reports the knob.
+    /// Rotations fire once per detent. While the knob is held down the `held_` bindings are used
+    /// instead, and `press` fires on release only if the knob wasn't turned in between.
+    #[derive(Serialize, Deserialize, Clone, Debug, Default)]
+    pub struct EncoderConfig {
+        #[serde(default)]
+        pub clockwise: Option<KeyAction>,
+
+        #[serde(default)]
+        pub counter_clockwise: Option<KeyAction>,
+
+        #[serde(default)]
+        pub press: Option<KeyAction>,
+
+        #[serde(default)]
+        pub held_clockwise: Option<KeyAction>,
+
+        #[serde(default)]
+        pub held_counter_clockwise: Option<KeyAction>,
+    }
+
+    impl EncoderConfig {
+        pub fn action(&self, input: EncoderInput) -> Option<KeyAction> {
+            match input {
+                EncoderInput::Clockwise => self.clockwise.clone(),
+                EncoderInput::CounterClockwise => self.counter_clockwise.clone(),
+                EncoderInput::Press => self.press.clone(),
+                EncoderInput::HeldClockwise => self.held_clockwise.clone(),
+                EncoderInput::HeldCounterClockwise => self.held_counter_clockwise.clone(),
+            }
+        }
+
+        /// These bindings, with any left unset taken from `base`.
+        fn layer_over(&self, base: &EncoderConfig) -> EncoderConfig {
+            EncoderConfig {
+                clockwise: self.clockwise.clone().or(base.clockwise.clone()),
+                counter_clockwise: self
+                    .counter_clockwise
+                    .clone()
+                    .or(base.counter_clockwise.clone()),
+                press: self.press.clone().or(base.press.clone()),
+                held_clockwise: self.held_clockwise.clone().or(base.held_clockwise.clone()),
+                held_counter_clockwise: self
+                    .held_counter_clockwise
+                    .clone()
+                    .or(base.held_counter_clockwise.clone()),
+            }
+        }
+    }
+
+    /// Picks a mode for a focused window. Every field that is set has to match.
+    #[derive(Serialize, Deserialize, Clone, Debug, Default)]
+    pub struct FocusRule {
+        /// Compared case-insensitively against either part of WM_CLASS.
+        #[serde(default)]
+        pub class: Option<String>,
+
+        /// Regex searched for in the window title.
+        #[serde(default)]
+        title: Option<String>,
+
+        /// Name of the window's process, as in `/proc/<pid>/comm`.
+        #[serde(default)]
+        pub process: Option<String>,
+
+        /// `title` compiled, the first time it is checked or matched.
+        #[serde(skip)]
+        pattern: OnceLock<Result<Regex, String>>,
+    }
+
+    impl FocusRule {
+        fn pattern(&self) -> Option<&Result<Regex, String>> {
+            let title = self.title.as_ref()?;
+            Some(
+                self.pattern
+                    .get_or_init(|| Regex::new(title).map_err(|e| e.to_string())),
+            )
+        }
+
+        /// Checks that the title pattern is a valid regex.
+        pub fn check(&self) -> Result<(), String> {
+            match self.pattern() {
+                Some(Err(reason)) => Err(format!("Invalid title pattern: {reason}")),
+                _ => Ok(()),
+            }
+        }
+
+        pub fn matches(&self, window: &FocusedWindow) -> bool {
+            let class = self
+                .class
+                .as_ref()
+                .is_none_or(|class| window.class.iter().any(|c| c.eq_ignore_ascii_case(class)));
+            let title = match self.pattern() {
+                Some(Ok(pattern)) => pattern.is_match(&window.title),
+                Some(Err(_)) => false,
+                None => true,
+            };
+            let process = self
+                .process
+                .as_ref()
+                .is_none_or(|process| window.process.as_ref() == Some(process));
+            class && title && process
+        }
+    }
+
     #[derive(Serialize, Deserialize, Clone, Debug)]
     pub struct AppModeConfig {
         key: String,
         title: String,
         title_short: String,
         color: Option<Color>,
-        keys: Vec<Option<KeyConfig>>
+        keys: Vec<Option<KeyConfig>>,
+
+        #[serde(default)]
+        encoder: Option<EncoderConfig>,
+
+        /// Switch to this mode when a window matching any of these gains focus.
+        #[serde(default)]
+        focus: Vec<FocusRule>,
+
+        /// Breaks ties when several modes match the focused window. Higher wins.
+        #[serde(default)]
+        priority: i32,
+
+        /// A mode to take unset keys, the color and encoder bindings from. Focus rules aren't
+        /// inherited. A key or encoder binding set to `{"type": "none"}` (with an empty label, for
+        /// keys) stays empty instead.
+        #[serde(default)]
+        parent: Option<String>,
     }
 
-    impl PadCompat<AppModeConfig, ModeConfig> for AppModeConfig {
-        fn to_pad(&self) -> ModeConfig {
+    impl AppModeConfig {
+        /// The mode as the pad stores it, leaving off chords `delivery` sends from the host.
+        pub fn to_pad_for(&self, delivery: Delivery) -> ModeConfig {
             ModeConfig {
                 key: self.key.clone(),
                 title: self.title.clone(),
                 title_short: self.title_short.clone(),
-                keys: self.keys.iter().map(|v| {
-                    if let Some(key) = v {
-                        Some(key.to_pad())
-                    } else {
-                        None
-                    }
-                }).collect(),
-                color: self.color
+                keys: self
+                    .keys
+                    .iter()
+                    .map(|v| {
+                        if let Some(key) = v {
+                            Some(key.to_pad_for(delivery))
+                        } else {
+                            None
+                        }
+                    })
+                    .collect(),
+                color: self.color,
             }
         }
+    }
+
+    impl PadCompat<AppModeConfig, ModeConfig> for AppModeConfig {
+        fn to_pad(&self) -> ModeConfig {
+            self.to_pad_for(Delivery::PadHid)
+        }
 
         fn from_pad(value: ModeConfig) -> AppModeConfig {
             AppModeConfig {
@@ -104,40 +397,319 @@ pub mod configuration {
                 title: value.title,
                 title_short: value.title_short,
                 color: value.color,
-                keys: value.keys.iter().map(|v| {
-                    if let Some(key) = v {
-                        Some(KeyConfig::from_pad(key.clone()))
-                    } else {
-                        None
-                    }
-                }).collect()
+                keys: value
+                    .keys
+                    .iter()
+                    .map(|v| {
+                        if let Some(key) = v {
+                            Some(KeyConfig::from_pad(key.clone()))
+                        } else {
+                            None
+                        }
+                    })
+                    .collect(),
+                encoder: None,
+                focus: Vec::new(),
+                priority: 0,
+                parent: None,
             }
         }
     }
 
+    /// How differences between the local config and the pad's `db.json` are settled on connect.
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+    pub enum SyncPolicy {
+        #[default]
+        #[serde(rename = "client_wins")]
+        ClientWins,
+
+        #[serde(rename = "pad_wins")]
+        PadWins,
+
+        #[serde(rename = "prompt")]
+        Prompt,
+    }
+
+    impl AppModeConfig {
+        pub fn key(&self) -> String {
+            self.key.clone()
+        }
+
+        /// Checks every chord the mode's keys and encoder press, naming the first one that is invalid.
+        pub fn check_chords(&self) -> Result<(), String> {
+            let keys = self.keys.iter().enumerate().filter_map(|(index, key)| {
+                key.as_ref()
+                    .map(|k| (format!("Key {} ({})", index, k.label), &k.action))
+            });
+            let encoder = self.encoder.iter().flat_map(|encoder| {
+                [
+                    ("clockwise", &encoder.clockwise),
+                    ("counter_clockwise", &encoder.counter_clockwise),
+                    ("press", &encoder.press),
+                    ("held_clockwise", &encoder.held_clockwise),
+                    ("held_counter_clockwise", &encoder.held_counter_clockwise),
+                ]
+                .into_iter()
+                .filter_map(|(input, action)| {
+                    action.as_ref().map(|a| (format!("Encoder {input}"), a))
+                })
+            });
+
+            for (place, action) in keys.chain(encoder) {
+                for chord in action.chords() {
+                    parse_chord(chord).map_err(|e| format!("{place}: {e}"))?;
+                }
+            }
+            Ok(())
+        }
+
+        /// Checks the title pattern of every focus rule, naming the first one that is invalid.
+        pub fn check_focus(&self) -> Result<(), String> {
+            for (index, rule) in self.focus.iter().enumerate() {
+                rule.check()
+                    .map_err(|e| format!("Focus rule {index}: {e}"))?;
+            }
+            Ok(())
+        }
+
+        pub fn parent(&self) -> Option<String> {
+            self.parent.clone()
+        }
+
+        /// Carries over the host-only parts of `local`, for when this mode was rebuilt from the pad.
+        pub fn with_host_bindings(mut self, local: &AppModeConfig) -> AppModeConfig {
+            self.encoder = local.encoder.clone();
+            self.focus = local.focus.clone();
+            self.priority = local.priority;
+            self.parent = local.parent.clone();
+            self
+        }
+
+        /// This mode with its unset keys, color and encoder bindings filled in from `base`.
+        fn layer_over(&self, base: &AppModeConfig) -> AppModeConfig {
+            let length = self.keys.len().max(base.keys.len());
+            AppModeConfig {
+                key: self.key.clone(),
+                title: self.title.clone(),
+                title_short: self.title_short.clone(),
+                color: self.color.or(base.color),
+                keys: (0..length)
+                    .map(|i| match self.keys.get(i).cloned().flatten() {
+                        Some(key) if key.is_blank() => None,
+                        Some(key) => Some(key),
+                        None => base.keys.get(i).cloned().flatten(),
+                    })
+                    .collect(),
+                encoder: match (&self.encoder, &base.encoder) {
+                    (Some(own), Some(inherited)) => Some(own.layer_over(inherited)),
+                    (own, inherited) => own.clone().or(inherited.clone()),
+                },
+                focus: self.focus.clone(),
+                priority: self.priority,
+                parent: None,
+            }
+        }
+    }
+
+    /// A known pad, keyed by its USB serial number.
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct DeviceConfig {
+        pub device: String,
+        pub port: Option<String>,
+        pub rate: Option<u32>,
+
+        /// Keys of the modes pushed to this pad. `None` means every mode.
+        pub modes: Option<Vec<String>>,
+
+        #[serde(default)]
+        pub identity: Option<DeviceIdentity>,
+    }
+
+    /// The colors and modes of a profile that isn't active.
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct ProfileConfig {
+        pub colors: ColorsConfig,
+        pub modes: Vec<AppModeConfig>,
+    }
+
+    /// A change to the config that can be undone. [`AppConfig::apply`] makes the change and
+    /// returns the edit that reverses it.
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    #[serde(tag = "type")]
+    pub enum ConfigEdit {
+        #[serde(rename = "set_color")]
+        SetColor { color: SetColorType },
+
+        #[serde(rename = "write_mode")]
+        WriteMode { mode: Box<AppModeConfig> },
+
+        #[serde(rename = "delete_mode")]
+        DeleteMode { key: String },
+
+        #[serde(rename = "clear_modes")]
+        ClearModes {},
+
+        /// Replaces every mode, keeping their order. This is how mode edits are undone.
+        #[serde(rename = "set_modes")]
+        SetModes { modes: Vec<AppModeConfig> },
+
+        #[serde(rename = "include_mode")]
+        IncludeMode { device: String, key: String },
+
+        #[serde(rename = "exclude_mode")]
+        ExcludeMode { device: String, key: String },
+
+        #[serde(rename = "set_device_modes")]
+        SetDeviceModes {
+            device: String,
+            modes: Option<Vec<String>>,
+        },
+    }
+
+    fn default_profile() -> String {
+        "default".to_string()
+    }
+
     #[derive(Serialize, Deserialize, Clone)]
     pub struct AppConfig {
-        pub device_port: Option<String>,
-        pub device_rate: Option<u32>,
+        /// The layout of this document, see [`SCHEMA_VERSION`].
+        #[serde(default)]
+        pub schema_version: u32,
+
+        #[serde(default)]
+        pub devices: Vec<DeviceConfig>,
         pub colors: ColorsConfig,
-        pub modes: Vec<AppModeConfig>
+        pub modes: Vec<AppModeConfig>,
+
+        #[serde(default)]
+        pub sync_policy: SyncPolicy,
+
+        /// Switch modes to follow the focused desktop window.
+        #[serde(default)]
+        pub auto_switch: bool,
+
+        #[serde(default)]
+        pub keyboard_layout: KeyboardLayout,
+
+        #[serde(default)]
+        pub text_fallback: TextFallback,
+
+        /// How actions deliver input unless they say otherwise.
+        #[serde(default)]
+        pub delivery: Delivery,
+
+        /// The profile `colors` and `modes` belong to.
+        #[serde(default = "default_profile")]
+        pub profile: String,
+
+        /// Every other profile, by name.
+        #[serde(default)]
+        pub profiles: BTreeMap<String, ProfileConfig>,
+
+        /// Globs, relative to the config directory, of files holding one mode each. The modes
+        /// are added after the ones in the config itself, and saved back to their own files.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub include: Vec<String>,
+
+        /// Which file each included mode came from, by key.
+        #[serde(skip)]
+        pub sources: BTreeMap<String, PathBuf>,
+
+        /// Why includes were left out when the config was read. Saving then would drop their
+        /// modes, so the config isn't saved while there are any.
+        #[serde(skip)]
+        pub include_errors: Vec<String>,
+
+        /// Fields this version doesn't know, kept so a newer client's settings survive a save.
+        #[serde(flatten)]
+        unknown: Map<String, Value>,
+    }
+
+    /// The `schema_version` written to the config file. Bump it along with a new entry in
+    /// [`MIGRATIONS`] whenever a change would stop older files from parsing.
+    pub const SCHEMA_VERSION: u32 = 1;
+
+    /// `MIGRATIONS[n]` upgrades a document from version `n` to `n + 1`. Files from before
+    /// versioning count as version 0.
+    const MIGRATIONS: &[fn(&mut Map<String, Value>)] = &[migrate_single_device];
+
+    /// 0 to 1: the single `device_port`/`device_rate` connection becomes the first of `devices`,
+    /// named by its port. Its identity is left for the next connect or hotplug to fill in.
+    fn migrate_single_device(document: &mut Map<String, Value>) {
+        let port = document.remove("device_port");
+        let rate = document.remove("device_rate").unwrap_or(Value::Null);
+
+        if let Some(Value::String(port)) = port {
+            let devices = document.entry("devices").or_insert_with(|| json!([]));
+            if devices.as_array().is_some_and(|d| d.is_empty()) {
+                *devices = json!([{
+                    "device": port,
+                    "port": port,
+                    "rate": rate,
+                    "modes": null,
+                    "identity": null,
+                }]);
+            }
+        }
+    }
+
+    /// The schema version of a config document, 0 if it has none.
+    pub fn document_version(document: &Value) -> u32 {
+        document
+            .get("schema_version")
+            .and_then(Value::as_u64)
+            .map_or(0, |version| version as u32)
+    }
+
+    /// Runs every migration `document` hasn't had yet, in order. Documents from a newer version
+    /// are left as they are and keep their version, so that version doesn't migrate them again.
+    pub fn migrate(mut document: Value) -> Value {
+        let version = document_version(&document);
+        if let Some(fields) = document.as_object_mut() {
+            for step in MIGRATIONS.iter().skip(version as usize) {
+                step(fields);
+            }
+            fields.insert(
+                "schema_version".to_string(),
+                json!(version.max(SCHEMA_VERSION)),
+            );
+        }
+        document
     }
 
     impl PadCompat<AppConfig, PadConfig> for AppConfig {
         fn to_pad(&self) -> PadConfig {
             PadConfig {
                 colors: self.colors.clone(),
-                modes: self.modes.iter().map(|v| v.to_pad()).collect()
+                modes: self
+                    .modes
+                    .iter()
+                    .map(|v| self.flatten(v).to_pad_for(self.delivery))
+                    .collect(),
             }
         }
 
         fn from_pad(value: PadConfig) -> AppConfig {
             AppConfig {
-                device_port: None,
-                device_rate: None,
+                schema_version: SCHEMA_VERSION,
+                devices: Vec::new(),
                 colors: value.colors.clone(),
-                modes: value.modes.iter().map(|v| AppModeConfig::from_pad(v.clone())).collect()
-
+                modes: value
+                    .modes
+                    .iter()
+                    .map(|v| AppModeConfig::from_pad(v.clone()))
+                    .collect(),
+                sync_policy: SyncPolicy::default(),
+                auto_switch: false,
+                keyboard_layout: KeyboardLayout::default(),
+                text_fallback: TextFallback::default(),
+                delivery: Delivery::default(),
+                profile: default_profile(),
+                profiles: BTreeMap::new(),
+                include: Vec::new(),
+                sources: BTreeMap::new(),
+                include_errors: Vec::new(),
+                unknown: Map::new(),
             }
         }
     }
@@ -145,60 +717,296 @@ pub mod configuration {
     impl AppConfig {
         pub fn default() -> AppConfig {
             AppConfig {
-                device_port: None,
-                device_rate: None,
-                colors: ColorsConfig { next: (0, 0, 0), previous: (0, 0, 0), select: (0, 0, 0), brightness: 1.0 },
-                modes: Vec::new()
+                schema_version: SCHEMA_VERSION,
+                devices: Vec::new(),
+                colors: ColorsConfig {
+                    next: (0, 0, 0),
+                    previous: (0, 0, 0),
+                    select: (0, 0, 0),
+                    brightness: 1.0,
+                },
+                modes: Vec::new(),
+                sync_policy: SyncPolicy::default(),
+                auto_switch: false,
+                keyboard_layout: KeyboardLayout::default(),
+                text_fallback: TextFallback::default(),
+                delivery: Delivery::default(),
+                profile: default_profile(),
+                profiles: BTreeMap::new(),
+                include: Vec::new(),
+                sources: BTreeMap::new(),
+                include_errors: Vec::new(),
+                unknown: Map::new(),
             }
         }
 
+        /// Parses a config document of any schema version, migrating it to the current one.
         pub fn from_json(value: &str) -> Result<AppConfig, Error> {
-            serde_json::from_str::<AppConfig>(value)
+            serde_json::from_str::<Value>(value).and_then(AppConfig::from_document)
+        }
+
+        /// Reads a config document already parsed from any format, migrating it like
+        /// [`AppConfig::from_json`].
+        pub fn from_document(document: Value) -> Result<AppConfig, Error> {
+            serde_json::from_value(migrate(document))
         }
 
         pub fn to_json(&self) -> Result<String, Error> {
             serde_json::to_string(self)
         }
 
-        pub fn load_config(app: AppHandle) -> AppConfig {
-            let mut path = app.path_resolver().app_config_dir().expect("Unable to resolve config path");
-            path.push("config.json");
-            if path.exists() {
-                let data = read_to_string(path).expect("Unable to read config file (already exists)");
-                AppConfig::from_json(data.as_str()).expect("Unable to parse config file.")
-            } else {
-                AppConfig::default().save(app)
+        /// Loads the config file, falling back to a backup or the defaults if it is unreadable.
+        pub fn load_config(app: PadRuntime) -> AppConfig {
+            match config_store::load_config(&app.config_dir()) {
+                Some((config, events)) if events.is_empty() => config,
+                Some((config, events)) => {
+                    for event in events {
+                        config_store::report(&app, event);
+                    }
+                    if config.include_errors.is_empty() {
+                        config.save(app)
+                    } else {
+                        config
+                    }
+                }
+                None => AppConfig::default().save(app),
             }
         }
 
-        pub fn save(&self, app: AppHandle) -> AppConfig {
-            let mut path = app.path_resolver().app_config_dir().expect("Unable to resolve config path");
-            if !path.exists() {
-                create_dir_all(path.clone()).expect("Unable to create config directory");
+        /// Backs up the previous file, then writes the config file atomically. Failures are
+        /// reported rather than fatal, as the config in memory is still good.
+        pub fn save(&self, app: PadRuntime) -> AppConfig {
+            if !self.include_errors.is_empty() {
+                let reason = format!(
+                    "Not saved until the include errors are fixed: {}",
+                    self.include_errors.join("; ")
+                );
+                config_store::report(&app, ConfigEvent::SaveFailed { reason });
+                return self.clone();
+            }
+            if let Err(error) = config_store::back_up(&app.config_dir()) {
+                let reason = format!("Unable to back up config: {error}");
+                config_store::report(&app, ConfigEvent::BackupFailed { reason });
+            }
+            if let Err(reason) = config_store::save_config(&app.config_dir(), self) {
+                config_store::report(&app, ConfigEvent::SaveFailed { reason });
             }
-            path.push("config.json");
-            let mut file = File::create(path).expect("Unable to open config file for writing.");
-            file.write_all(self.to_json().expect("Unable to serialize config").as_bytes()).expect("Unable to write data");
             self.clone()
         }
 
-        pub fn set_connection(&mut self, port: String, rate: u32) -> AppConfig {
-            self.device_port = Some(port);
-            self.device_rate = Some(rate);
+        pub fn device(&self, device: &str) -> Option<DeviceConfig> {
+            self.devices.iter().find(|d| d.device == device).cloned()
+        }
+
+        fn device_mut(&mut self, device: String) -> &mut DeviceConfig {
+            if let Some(index) = self.devices.iter().position(|d| d.device == device) {
+                &mut self.devices[index]
+            } else {
+                self.devices.push(DeviceConfig {
+                    device,
+                    port: None,
+                    rate: None,
+                    modes: None,
+                    identity: None,
+                });
+                self.devices.last_mut().unwrap()
+            }
+        }
+
+        pub fn set_connection(&mut self, device: String, port: String, rate: u32) -> AppConfig {
+            let entry = self.device_mut(device);
+            entry.port = Some(port);
+            entry.rate = Some(rate);
             self.clone()
         }
 
-        pub fn clear_connection(&mut self) -> AppConfig {
-            self.device_port = None;
-            self.device_rate = None;
+        pub fn clear_connection(&mut self, device: String) -> AppConfig {
+            let entry = self.device_mut(device);
+            entry.port = None;
+            entry.rate = None;
             self.clone()
         }
 
+        pub fn set_identity(&mut self, device: String, identity: DeviceIdentity) -> AppConfig {
+            self.device_mut(device).identity = Some(identity);
+            self.clone()
+        }
+
+        /// The saved device with this USB identity, if any.
+        pub fn device_by_identity(&self, identity: &DeviceIdentity) -> Option<DeviceConfig> {
+            self.devices
+                .iter()
+                .find(|d| {
+                    d.identity
+                        .as_ref()
+                        .is_some_and(|saved| saved.matches(identity))
+                })
+                .cloned()
+        }
+
+        pub fn set_device_modes(
+            &mut self,
+            device: String,
+            modes: Option<Vec<String>>,
+        ) -> AppConfig {
+            self.device_mut(device).modes = modes;
+            self.clone()
+        }
+
+        pub fn device_allows_mode(&self, device: &str, mode: &str) -> bool {
+            match self.device(device).and_then(|d| d.modes) {
+                Some(modes) => modes.iter().any(|m| m == mode),
+                None => true,
+            }
+        }
+
+        /// Adds a mode to a device's mode set. Devices that take every mode are left alone.
+        pub fn include_mode(&mut self, device: String, mode: String) -> AppConfig {
+            if let Some(modes) = self.device_mut(device).modes.as_mut() {
+                if !modes.contains(&mode) {
+                    modes.push(mode);
+                }
+            }
+            self.clone()
+        }
+
+        /// Removes a mode from a device's mode set. A device that takes every mode is given a set of
+        /// all the others, so the mode stays on every other pad.
+        pub fn exclude_mode(&mut self, device: String, mode: String) -> AppConfig {
+            let all: Vec<String> = self.modes.iter().map(|m| m.key()).collect();
+            self.device_mut(device)
+                .modes
+                .get_or_insert(all)
+                .retain(|m| *m != mode);
+            self.clone()
+        }
+
+        /// The config as seen by one device, with modes outside its mode set left out. Modes are
+        /// flattened, as a parent may be one of those left out.
+        pub fn device_view(&self, device: &str) -> AppConfig {
+            let mut view = self.clone();
+            view.modes = self
+                .modes
+                .iter()
+                .filter(|m| self.device_allows_mode(device, &m.key))
+                .map(|m| self.flatten(m))
+                .collect();
+            view
+        }
+
+        /// `mode` with everything it inherits resolved, as the pad and the executor see it. A
+        /// missing parent or a loop ends the chain there.
+        pub fn flatten(&self, mode: &AppModeConfig) -> AppModeConfig {
+            let mut chain = vec![mode];
+            let mut seen = HashSet::from([mode.key.as_str()]);
+            while let Some(parent) = chain.last().and_then(|m| m.parent.as_deref()) {
+                match self.modes.iter().find(|m| m.key == parent) {
+                    Some(next) if seen.insert(next.key.as_str()) => chain.push(next),
+                    _ => break,
+                }
+            }
+
+            let root = chain.pop().map(|m| AppModeConfig {
+                parent: None,
+                ..m.clone()
+            });
+            chain
+                .iter()
+                .rev()
+                .fold(root.unwrap_or_else(|| mode.clone()), |base, m| {
+                    m.layer_over(&base)
+                })
+        }
+
+        /// Checks that `mode` can be written: its parent has to exist, and can't lead back to it.
+        pub fn check_parent(&self, mode: &AppModeConfig) -> Result<(), &'static str> {
+            let mut seen = HashSet::from([mode.key.as_str()]);
+            let mut next = mode.parent.as_deref();
+            while let Some(parent) = next {
+                if !seen.insert(parent) {
+                    return Err("Mode would inherit from itself");
+                }
+                let Some(found) = self.modes.iter().find(|m| m.key == parent) else {
+                    return Err("Parent mode doesn't exist");
+                };
+                next = found.parent.as_deref();
+            }
+            Ok(())
+        }
+
+        /// Keys of every mode inheriting from `key`, directly or through other modes.
+        pub fn descendants(&self, key: &str) -> Vec<String> {
+            let mut found: Vec<String> = Vec::new();
+            let mut queue = vec![key.to_string()];
+            while let Some(parent) = queue.pop() {
+                for child in self
+                    .modes
+                    .iter()
+                    .filter(|m| m.parent.as_deref() == Some(parent.as_str()))
+                {
+                    if child.key != key && !found.contains(&child.key) {
+                        found.push(child.key.clone());
+                        queue.push(child.key.clone());
+                    }
+                }
+            }
+            found
+        }
+
+        /// The pad's form of the mode `key`, flattened, if there is one.
+        pub fn mode_to_pad(&self, key: &str) -> Option<ModeConfig> {
+            self.modes
+                .iter()
+                .find(|m| m.key == key)
+                .map(|m| self.flatten(m).to_pad_for(self.delivery))
+        }
+
+        /// Lets keys and the color of a mode rebuilt from the pad inherit again where the pad has
+        /// exactly what its parent would give it. Slots the pad has empty where the parent has a
+        /// key are kept blank.
+        pub fn relayer(&self, mut mode: AppModeConfig) -> AppModeConfig {
+            let Some(parent) = mode
+                .parent
+                .as_deref()
+                .and_then(|p| self.modes.iter().find(|m| m.key == p))
+            else {
+                return mode;
+            };
+            let inherited = self.flatten(parent);
+            let inherited_pad = inherited.to_pad_for(self.delivery);
+
+            for (i, key) in mode.keys.iter_mut().enumerate() {
+                let from_parent = inherited_pad.keys.get(i).cloned().flatten();
+                if key.is_none() && from_parent.is_some() {
+                    *key = Some(KeyConfig::blank());
+                } else if key.is_some()
+                    && key.as_ref().map(|k| k.to_pad_for(self.delivery)) == from_parent
+                {
+                    *key = None;
+                }
+            }
+            if mode.color.is_some() && mode.color == inherited.color {
+                mode.color = None;
+            }
+            mode
+        }
+
         pub fn set(&mut self, update: AppConfig) -> AppConfig {
-            self.device_port = update.device_port;
-            self.device_rate = update.device_rate;
+            self.schema_version = update.schema_version;
+            self.unknown = update.unknown;
+            self.devices = update.devices;
             self.colors = update.colors;
             self.modes = update.modes;
+            self.sync_policy = update.sync_policy;
+            self.auto_switch = update.auto_switch;
+            self.keyboard_layout = update.keyboard_layout;
+            self.text_fallback = update.text_fallback;
+            self.delivery = update.delivery;
+            self.profile = update.profile;
+            self.profiles = update.profiles;
+            self.include = update.include;
+            self.sources = update.sources;
+            self.include_errors = update.include_errors;
             self.clone()
         }
 
@@ -213,25 +1021,27 @@ pub mod configuration {
         }
 
         pub fn write_mode(&mut self, mode: AppModeConfig) -> AppConfig {
-            self.modes = self.modes.iter().filter_map(|m| {
-                if m.key == mode.key {
-                    None
-                } else {
-                    Some(m.clone())
-                }
-            }).collect();
+            self.modes = self
+                .modes
+                .iter()
+                .filter_map(|m| {
+                    if m.key == mode.key {
+                        None
+                    } else {
+                        Some(m.clone())
+                    }
+                })
+                .collect();
             self.modes.push(mode);
             self.clone()
         }
 
         pub fn delete_mode(&mut self, mode: String) -> AppConfig {
-            self.modes = self.modes.iter().filter_map(|m| {
-                if m.key == mode {
-                    None
-                } else {
-                    Some(m.clone())
-                }
-            }).collect();
+            self.modes = self
+                .modes
+                .iter()
+                .filter_map(|m| if m.key == mode { None } else { Some(m.clone()) })
+                .collect();
             self.clone()
         }
 
@@ -239,5 +1049,422 @@ pub mod configuration {
             self.modes.clear();
             self.clone()
         }
+
+        fn color(&self, like: &SetColorType) -> SetColorType {
+            match like {
+                SetColorType::Next { .. } => SetColorType::Next {
+                    color: self.colors.next,
+                },
+                SetColorType::Previous { .. } => SetColorType::Previous {
+                    color: self.colors.previous,
+                },
+                SetColorType::Select { .. } => SetColorType::Select {
+                    color: self.colors.select,
+                },
+                SetColorType::Brightness { .. } => SetColorType::Brightness {
+                    color: self.colors.brightness,
+                },
+            }
+        }
+
+        /// Makes `edit`, returning the edit that puts things back as they were.
+        pub fn apply(&mut self, edit: ConfigEdit) -> ConfigEdit {
+            let modes = ConfigEdit::SetModes {
+                modes: self.modes.clone(),
+            };
+            let device_modes = |config: &AppConfig, device: &str| ConfigEdit::SetDeviceModes {
+                device: device.to_string(),
+                modes: config.device(device).and_then(|d| d.modes),
+            };
+
+            match edit {
+                ConfigEdit::SetColor { color } => {
+                    let previous = self.color(&color);
+                    self.set_color(color);
+                    ConfigEdit::SetColor { color: previous }
+                }
+                ConfigEdit::WriteMode { mode } => {
+                    self.write_mode(*mode);
+                    modes
+                }
+                ConfigEdit::DeleteMode { key } => {
+                    self.delete_mode(key);
+                    modes
+                }
+                ConfigEdit::ClearModes {} => {
+                    self.clear_modes();
+                    modes
+                }
+                ConfigEdit::SetModes { modes: update } => {
+                    self.modes = update;
+                    modes
+                }
+                ConfigEdit::IncludeMode { device, key } => {
+                    let previous = device_modes(self, &device);
+                    self.include_mode(device, key);
+                    previous
+                }
+                ConfigEdit::ExcludeMode { device, key } => {
+                    let previous = device_modes(self, &device);
+                    self.exclude_mode(device, key);
+                    previous
+                }
+                ConfigEdit::SetDeviceModes {
+                    device,
+                    modes: update,
+                } => {
+                    let previous = device_modes(self, &device);
+                    self.set_device_modes(device, update);
+                    previous
+                }
+            }
+        }
+
+        /// Makes every edit in order, returning the edits that reverse them all, also in order.
+        pub fn apply_all(&mut self, edits: Vec<ConfigEdit>) -> Vec<ConfigEdit> {
+            let mut undo: Vec<ConfigEdit> =
+                edits.into_iter().map(|edit| self.apply(edit)).collect();
+            undo.reverse();
+            undo
+        }
+
+        pub fn set_sync_policy(&mut self, policy: SyncPolicy) -> AppConfig {
+            self.sync_policy = policy;
+            self.clone()
+        }
+
+        pub fn set_auto_switch(&mut self, enabled: bool) -> AppConfig {
+            self.auto_switch = enabled;
+            self.clone()
+        }
+
+        pub fn set_keyboard_layout(
+            &mut self,
+            layout: KeyboardLayout,
+            fallback: Option<TextFallback>,
+        ) -> AppConfig {
+            self.keyboard_layout = layout;
+            if let Some(fallback) = fallback {
+                self.text_fallback = fallback;
+            }
+            self.clone()
+        }
+
+        pub fn set_delivery(&mut self, delivery: Delivery) -> AppConfig {
+            self.delivery = delivery;
+            self.clone()
+        }
+
+        /// Every profile name, the active one included, in order.
+        pub fn profile_names(&self) -> Vec<String> {
+            let mut names: Vec<String> = self.profiles.keys().cloned().collect();
+            names.push(self.profile.clone());
+            names.sort();
+            names
+        }
+
+        fn has_profile(&self, name: &str) -> bool {
+            self.profile == name || self.profiles.contains_key(name)
+        }
+
+        fn check_new_profile(&self, name: &str) -> Result<(), &'static str> {
+            if name.trim().is_empty() {
+                Err("Profile name can't be empty")
+            } else if self.has_profile(name) {
+                Err("Profile already exists")
+            } else {
+                Ok(())
+            }
+        }
+
+        /// Adds a profile with the active profile's colors and no modes.
+        pub fn create_profile(&mut self, name: String) -> Result<AppConfig, &'static str> {
+            self.check_new_profile(&name)?;
+            self.profiles.insert(
+                name,
+                ProfileConfig {
+                    colors: self.colors.clone(),
+                    modes: Vec::new(),
+                },
+            );
+            Ok(self.clone())
+        }
+
+        pub fn clone_profile(
+            &mut self,
+            source: String,
+            name: String,
+        ) -> Result<AppConfig, &'static str> {
+            self.check_new_profile(&name)?;
+            let copy = if source == self.profile {
+                ProfileConfig {
+                    colors: self.colors.clone(),
+                    modes: self.modes.clone(),
+                }
+            } else {
+                self.profiles
+                    .get(&source)
+                    .cloned()
+                    .ok_or("Profile doesn't exist")?
+            };
+            self.profiles.insert(name, copy);
+            Ok(self.clone())
+        }
+
+        pub fn rename_profile(
+            &mut self,
+            name: String,
+            new_name: String,
+        ) -> Result<AppConfig, &'static str> {
+            if !self.has_profile(&name) {
+                return Err("Profile doesn't exist");
+            }
+            self.check_new_profile(&new_name)?;
+            if name == self.profile {
+                self.profile = new_name;
+            } else if let Some(profile) = self.profiles.remove(&name) {
+                self.profiles.insert(new_name, profile);
+            }
+            Ok(self.clone())
+        }
+
+        /// Deletes a profile other than the active one.
+        pub fn delete_profile(&mut self, name: String) -> Result<AppConfig, &'static str> {
+            if name == self.profile {
+                return Err("Can't delete the active profile");
+            }
+            self.profiles.remove(&name).ok_or("Profile doesn't exist")?;
+            Ok(self.clone())
+        }
+
+        /// Makes `name` the active profile, putting the current colors and modes away under theirs.
+        pub fn activate_profile(&mut self, name: String) -> Result<AppConfig, &'static str> {
+            if name == self.profile {
+                return Ok(self.clone());
+            }
+            let next = self.profiles.remove(&name).ok_or("Profile doesn't exist")?;
+            let previous = ProfileConfig {
+                colors: replace(&mut self.colors, next.colors),
+                modes: replace(&mut self.modes, next.modes),
+            };
+            self.profiles
+                .insert(replace(&mut self.profile, name), previous);
+            Ok(self.clone())
+        }
+
+        /// The highest priority mode with a rule matching `window`. Earlier modes win ties.
+        pub fn mode_for_window(&self, window: &FocusedWindow) -> Option<String> {
+            self.modes
+                .iter()
+                .filter(|m| m.focus.iter().any(|rule| rule.matches(window)))
+                .min_by_key(|m| Reverse(m.priority))
+                .map(|m| m.key.clone())
+        }
+
+        pub fn resolve_action(&self, mode: String, index: usize) -> Option<KeyAction> {
+            self.modes
+                .iter()
+                .find(|m| m.key == mode)
+                .and_then(|m| self.flatten(m).keys.get(index).cloned().flatten())
+                .map(|k| k.action)
+        }
+
+        pub fn resolve_encoder_action(&self, mode: &str, input: EncoderInput) -> Option<KeyAction> {
+            self.modes
+                .iter()
+                .find(|m| m.key == mode)
+                .and_then(|m| self.flatten(m).encoder)
+                .and_then(|e| e.action(input))
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn window(class: &str, title: &str) -> FocusedWindow {
+            FocusedWindow {
+                class: vec![class.to_string()],
+                title: title.to_string(),
+                process: None,
+            }
+        }
+
+        #[test]
+        fn migrates_version_0_files() {
+            let config =
+                AppConfig::from_json(include_str!("../../tests/fixtures/config-v0.json")).unwrap();
+            assert_eq!(config.schema_version, SCHEMA_VERSION);
+            assert_eq!(config.devices.len(), 1);
+            let device = &config.devices[0];
+            assert_eq!(device.device, "/dev/ttyRATPAD-FIXTURE");
+            assert_eq!(device.port.as_deref(), Some("/dev/ttyRATPAD-FIXTURE"));
+            assert_eq!(device.rate, Some(115200));
+            assert!(device.modes.is_none() && device.identity.is_none());
+            assert!(config.unknown.is_empty());
+
+            assert_eq!(config.colors.next, (255, 0, 0));
+            assert_eq!(config.modes.len(), 1);
+            let mode = &config.modes[0];
+            assert_eq!(mode.key, "default");
+            assert!(mode.keys[1].is_none());
+            assert!(matches!(
+                mode.keys[0].as_ref().map(|k| &k.action),
+                Some(KeyAction::KeyPress { key, delivery: None }) if key == "CTRL+C"
+            ));
+            assert!(matches!(
+                mode.keys[2].as_ref().map(|k| &k.action),
+                Some(KeyAction::Command { execute, .. }) if execute == "kitty"
+            ));
+        }
+
+        #[test]
+        fn loads_version_1_files_unchanged() {
+            let text = include_str!("../../tests/fixtures/config-v1.json");
+            let config = AppConfig::from_json(text).unwrap();
+            assert_eq!(config.schema_version, 1);
+            assert_eq!(config.devices[0].device, "RP2040-0001");
+            assert_eq!(config.devices[0].modes, Some(vec!["default".to_string()]));
+            assert_eq!(
+                serde_json::to_value(&config).unwrap(),
+                serde_json::from_str::<Value>(text).unwrap()
+            );
+        }
+
+        fn key(label: &str) -> Value {
+            json!({"label": label, "action": {"type": "keypress", "key": label}, "color": null})
+        }
+
+        fn with_modes(modes: Value) -> AppConfig {
+            AppConfig::from_document(json!({
+                "colors": {
+                    "next": [0, 0, 0],
+                    "previous": [0, 0, 0],
+                    "select": [0, 0, 0],
+                    "brightness": 1.0,
+                },
+                "modes": modes,
+            }))
+            .unwrap()
+        }
+
+        fn label(mode: &AppModeConfig, index: usize) -> Option<&str> {
+            mode.keys[index].as_ref().map(|k| k.label.as_str())
+        }
+
+        #[test]
+        fn flattens_inherited_keys_color_and_encoder() {
+            let config = with_modes(json!([
+                {
+                    "key": "base", "title": "Base", "title_short": "B", "color": [1, 1, 1],
+                    "keys": [key("A"), key("B"), key("C")],
+                    "encoder": {"clockwise": {"type": "keypress", "key": "UP"}},
+                    "focus": [{"class": "base"}],
+                },
+                {
+                    "key": "middle", "title": "Middle", "title_short": "M", "color": null,
+                    "keys": [null, key("X")],
+                    "encoder": {"press": {"type": "keypress", "key": "ENTER"}},
+                    "parent": "base",
+                },
+                {
+                    "key": "leaf", "title": "Leaf", "title_short": "L", "color": [2, 2, 2],
+                    "keys": [null, null, null, key("D")],
+                    "parent": "middle",
+                },
+            ]));
+
+            let leaf = config.flatten(&config.modes[2]);
+            assert_eq!(leaf.key, "leaf");
+            assert_eq!(leaf.color, Some((2, 2, 2)));
+            let labels: Vec<_> = (0..4).map(|i| label(&leaf, i)).collect();
+            assert_eq!(labels, [Some("A"), Some("X"), Some("C"), Some("D")]);
+            let encoder = leaf.encoder.unwrap();
+            assert!(encoder.clockwise.is_some() && encoder.press.is_some());
+            assert!(leaf.focus.is_empty() && leaf.parent.is_none());
+
+            let middle = config.flatten(&config.modes[1]);
+            assert_eq!(middle.color, Some((1, 1, 1)));
+            assert_eq!(config.descendants("base"), ["middle", "leaf"]);
+        }
+
+        #[test]
+        fn blank_keys_override_the_parent() {
+            let config = with_modes(json!([
+                {
+                    "key": "base", "title": "Base", "title_short": "B", "color": null,
+                    "keys": [key("A"), key("B")],
+                },
+                {
+                    "key": "child", "title": "Child", "title_short": "C", "color": null,
+                    "keys": [{"label": "", "action": {"type": "none"}, "color": null}],
+                    "parent": "base",
+                },
+            ]));
+
+            let child = config.flatten(&config.modes[1]);
+            assert_eq!(label(&child, 0), None);
+            assert_eq!(label(&child, 1), Some("B"));
+
+            // Rebuilt from the pad, the empty slot stays blank and the inherited key inherits.
+            let pad = config.mode_to_pad("child").unwrap();
+            assert!(pad.keys[0].is_none());
+            let rebuilt = AppModeConfig::from_pad(pad).with_host_bindings(&config.modes[1]);
+            let rebuilt = config.relayer(rebuilt);
+            assert!(rebuilt.keys[0].as_ref().is_some_and(KeyConfig::is_blank));
+            assert!(rebuilt.keys[1].is_none());
+        }
+
+        #[test]
+        fn stops_flattening_at_a_loop() {
+            let config = with_modes(json!([
+                {
+                    "key": "a", "title": "A", "title_short": "A", "color": null,
+                    "keys": [key("A"), null], "parent": "b",
+                },
+                {
+                    "key": "b", "title": "B", "title_short": "B", "color": null,
+                    "keys": [null, key("B")], "parent": "a",
+                },
+            ]));
+
+            let a = config.flatten(&config.modes[0]);
+            assert_eq!((label(&a, 0), label(&a, 1)), (Some("A"), Some("B")));
+            assert!(config.check_parent(&config.modes[0]).is_err());
+        }
+
+        #[test]
+        fn excluding_a_mode_leaves_other_devices_alone() {
+            let mut config = with_modes(json!([
+                {"key": "a", "title": "A", "title_short": "A", "color": null, "keys": []},
+                {"key": "b", "title": "B", "title_short": "B", "color": null, "keys": []},
+            ]));
+
+            config.apply(ConfigEdit::ExcludeMode {
+                device: "left".to_string(),
+                key: "a".to_string(),
+            });
+            assert_eq!(config.modes.len(), 2);
+            assert!(!config.device_allows_mode("left", "a"));
+            assert!(config.device_allows_mode("left", "b"));
+            assert!(config.device_allows_mode("right", "a"));
+        }
+
+        #[test]
+        fn focus_rules_match_every_field_set() {
+            let rule: FocusRule =
+                serde_json::from_value(json!({"class": "Firefox", "title": "- YouTube"})).unwrap();
+            assert!(rule.check().is_ok());
+            assert!(rule.matches(&window("firefox", "Video - YouTube")));
+            assert!(!rule.matches(&window("firefox", "Inbox")));
+            assert!(!rule.matches(&window("chromium", "Video - YouTube")));
+        }
+
+        #[test]
+        fn focus_rules_reject_invalid_patterns() {
+            let rule: FocusRule = serde_json::from_value(json!({"title": "foo("})).unwrap();
+            assert!(rule.check().is_err());
+            assert!(!rule.matches(&window("any", "foo(")));
+        }
     }
-}
\ No newline at end of file
+}
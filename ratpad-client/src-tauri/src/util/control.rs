@@ -0,0 +1,157 @@
+pub mod control_socket {
+    use std::path::{Path, PathBuf};
+
+    use crate::util::{
+        command_handler::{CommandReturnTypes, CommandTypes},
+        pad_runtime::PadRuntime,
+    };
+
+    /// Socket the running client listens on for [`CommandTypes`], one JSON object per line.
+    /// Each request gets one line back: `{"Ok": <CommandReturnTypes>}` or `{"Err": "<reason>"}`.
+    pub fn socket_path(config_dir: &Path) -> PathBuf {
+        config_dir.join("ratpad.sock")
+    }
+
+    #[cfg(unix)]
+    mod platform {
+        use std::{
+            fs::{
+                create_dir_all, remove_dir_all, remove_file, rename, set_permissions, DirBuilder,
+                Permissions,
+            },
+            io::{BufRead, BufReader, ErrorKind, Write},
+            os::unix::{
+                fs::{DirBuilderExt, PermissionsExt},
+                net::UnixStream,
+            },
+            path::Path,
+            process,
+        };
+
+        use tokio::{
+            io::{AsyncBufReadExt, AsyncWriteExt, BufReader as AsyncBufReader},
+            net::{UnixListener, UnixStream as AsyncUnixStream},
+            spawn,
+            task::spawn_blocking,
+        };
+
+        use crate::util::{
+            command_handler::{execute, CommandReturnTypes, CommandTypes},
+            pad_runtime::PadRuntime,
+        };
+
+        async fn serve_client(runtime: PadRuntime, stream: AsyncUnixStream) {
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = AsyncBufReader::new(reader).lines();
+
+            while let Ok(Some(line)) = lines.next_line().await {
+                let result: Result<CommandReturnTypes, String> =
+                    match serde_json::from_str::<CommandTypes>(&line) {
+                        Ok(command) => {
+                            let command_runtime = runtime.clone();
+                            // execute() blocks on pad replies, so keep it off the reactor.
+                            spawn_blocking(move || execute(command_runtime, command))
+                                .await
+                                .map_err(|e| e.to_string())
+                                .and_then(|r| r)
+                        }
+                        Err(error) => Err(format!("Invalid command: {error}")),
+                    };
+
+                if let Ok(mut reply) = serde_json::to_string(&result) {
+                    reply.push('\n');
+                    if writer.write_all(reply.as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+
+        /// Anything that can reach the socket can bind shell commands to keys. It is bound inside
+        /// a directory only the owner can enter and moved into place once its own permissions are
+        /// narrowed, so it is never reachable with the umask's.
+        fn bind_private(path: &Path) -> std::io::Result<UnixListener> {
+            let staging = path.with_extension(format!("{}.tmp", process::id()));
+            DirBuilder::new().mode(0o700).create(&staging)?;
+            let staged = staging.join("ratpad.sock");
+            let bound = UnixListener::bind(&staged).and_then(|listener| {
+                set_permissions(&staged, Permissions::from_mode(0o600))?;
+                rename(&staged, path)?;
+                Ok(listener)
+            });
+            let _ = remove_dir_all(&staging);
+            bound
+        }
+
+        pub fn start(runtime: PadRuntime, path: &Path) -> std::io::Result<()> {
+            if let Some(parent) = path.parent() {
+                create_dir_all(parent)?;
+            }
+            if path.exists() {
+                if UnixStream::connect(path).is_ok() {
+                    return Err(ErrorKind::AddrInUse.into());
+                }
+                remove_file(path)?;
+            }
+
+            let listener = bind_private(path)?;
+
+            spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    spawn(serve_client(runtime.clone(), stream));
+                }
+            });
+            Ok(())
+        }
+
+        pub fn send(path: &Path, command: &CommandTypes) -> Result<CommandReturnTypes, String> {
+            let mut stream = UnixStream::connect(path)
+                .map_err(|e| format!("Unable to reach ratpad at {}: {e}", path.display()))?;
+            let mut request = serde_json::to_string(command).map_err(|e| e.to_string())?;
+            request.push('\n');
+            stream
+                .write_all(request.as_bytes())
+                .map_err(|e| e.to_string())?;
+
+            let mut reply = String::new();
+            BufReader::new(stream)
+                .read_line(&mut reply)
+                .map_err(|e| e.to_string())?;
+            serde_json::from_str::<Result<CommandReturnTypes, String>>(&reply)
+                .map_err(|e| format!("Invalid reply: {e}"))?
+        }
+    }
+
+    #[cfg(not(unix))]
+    mod platform {
+        use std::path::Path;
+
+        use crate::util::{
+            command_handler::{CommandReturnTypes, CommandTypes},
+            pad_runtime::PadRuntime,
+        };
+
+        pub fn start(_runtime: PadRuntime, _path: &Path) -> std::io::Result<()> {
+            Ok(())
+        }
+
+        pub fn send(_path: &Path, _command: &CommandTypes) -> Result<CommandReturnTypes, String> {
+            Err("The control socket is only available on unix".to_string())
+        }
+    }
+
+    pub fn start_control_socket(runtime: &PadRuntime) {
+        let path = socket_path(&runtime.config_dir());
+        if let Err(error) = platform::start(runtime.clone(), &path) {
+            runtime.report_failure("control_socket", error);
+        }
+    }
+
+    /// Runs `command` in the client or daemon that owns the socket under `config_dir`.
+    pub fn send_control_command(
+        config_dir: &Path,
+        command: &CommandTypes,
+    ) -> Result<CommandReturnTypes, String> {
+        platform::send(&socket_path(config_dir), command)
+    }
+}
@@ -0,0 +1,648 @@
+pub mod action_executor {
+    use std::{
+        collections::HashMap,
+        future::Future,
+        pin::Pin,
+        process::Stdio,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex, OnceLock,
+        },
+        time::Duration,
+    };
+
+    use serde::{Deserialize, Serialize};
+    use tokio::{
+        process::Command,
+        spawn,
+        sync::Mutex as AsyncMutex,
+        task::AbortHandle,
+        time::{sleep, timeout},
+    };
+
+    use crate::{
+        ratpad_communication::PadEvent,
+        serial_client::SerialEvent,
+        util::{
+            configuration::{EncoderInput, KeyAction, MacroStep},
+            input_injection::{deliver, Delivery, Input},
+            keyboard_layout::translate,
+            pad_runtime::PadRuntime,
+        },
+    };
+
+    /// What fired an action: a key, by its index in the mode, or the encoder.
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+    #[serde(untagged)]
+    pub enum ActionSource {
+        Key { index: usize },
+        Encoder { encoder: EncoderInput },
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    #[serde(tag = "type")]
+    pub enum ActionEvent {
+        #[serde(rename = "action.unbound")]
+        Unbound {
+            mode: String,
+            #[serde(flatten)]
+            source: ActionSource,
+        },
+
+        #[serde(rename = "action.keypress")]
+        KeyPress {
+            mode: String,
+            #[serde(flatten)]
+            source: ActionSource,
+            key: String,
+        },
+
+        #[serde(rename = "action.typed")]
+        Typed {
+            mode: String,
+            #[serde(flatten)]
+            source: ActionSource,
+            text: String,
+        },
+
+        #[serde(rename = "action.started")]
+        Started {
+            mode: String,
+            #[serde(flatten)]
+            source: ActionSource,
+            execute: String,
+        },
+
+        #[serde(rename = "action.finished")]
+        Finished {
+            mode: String,
+            #[serde(flatten)]
+            source: ActionSource,
+            execute: String,
+            success: bool,
+            code: Option<i32>,
+            stdout: String,
+            stderr: String,
+        },
+
+        #[serde(rename = "action.timed_out")]
+        TimedOut {
+            mode: String,
+            #[serde(flatten)]
+            source: ActionSource,
+            execute: String,
+        },
+
+        #[serde(rename = "action.failed")]
+        Failed {
+            mode: String,
+            #[serde(flatten)]
+            source: ActionSource,
+            execute: String,
+            reason: String,
+        },
+
+        #[serde(rename = "action.macro_started")]
+        MacroStarted {
+            mode: String,
+            #[serde(flatten)]
+            source: ActionSource,
+        },
+
+        #[serde(rename = "action.macro_finished")]
+        MacroFinished {
+            mode: String,
+            #[serde(flatten)]
+            source: ActionSource,
+        },
+
+        #[serde(rename = "action.macro_cancelled")]
+        MacroCancelled {
+            mode: String,
+            #[serde(flatten)]
+            source: ActionSource,
+        },
+
+        #[serde(rename = "action.input_failed")]
+        InputFailed {
+            mode: String,
+            #[serde(flatten)]
+            source: ActionSource,
+            reason: String,
+        },
+    }
+
+    pub fn publish_action_event(handle: PadRuntime, event: ActionEvent) {
+        handle.emit("ratpad://action", event);
+    }
+
+    fn build_command(
+        execute: String,
+        args: Option<Vec<String>>,
+        cwd: Option<String>,
+        env: Option<HashMap<String, String>>,
+    ) -> Command {
+        let mut command = Command::new(execute);
+        command
+            .args(args.unwrap_or_default())
+            .envs(env.unwrap_or_default())
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(dir) = cwd {
+            command.current_dir(dir);
+        }
+        command
+    }
+
+    async fn run_command(
+        handle: PadRuntime,
+        mode: String,
+        source: ActionSource,
+        execute: String,
+        mut command: Command,
+        limit: Option<u64>,
+    ) {
+        let child = match command.spawn() {
+            Ok(child) => child,
+            Err(error) => {
+                publish_action_event(
+                    handle,
+                    ActionEvent::Failed {
+                        mode,
+                        source,
+                        execute,
+                        reason: error.to_string(),
+                    },
+                );
+                return;
+            }
+        };
+
+        publish_action_event(
+            handle.clone(),
+            ActionEvent::Started {
+                mode: mode.clone(),
+                source: source.clone(),
+                execute: execute.clone(),
+            },
+        );
+
+        let result = if let Some(ms) = limit {
+            match timeout(Duration::from_millis(ms), child.wait_with_output()).await {
+                Ok(output) => output,
+                Err(_) => {
+                    publish_action_event(
+                        handle,
+                        ActionEvent::TimedOut {
+                            mode,
+                            source,
+                            execute,
+                        },
+                    );
+                    return;
+                }
+            }
+        } else {
+            child.wait_with_output().await
+        };
+
+        match result {
+            Ok(output) => publish_action_event(
+                handle,
+                ActionEvent::Finished {
+                    mode,
+                    source,
+                    execute,
+                    success: output.status.success(),
+                    code: output.status.code(),
+                    stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+                    stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+                },
+            ),
+            Err(error) => publish_action_event(
+                handle,
+                ActionEvent::Failed {
+                    mode,
+                    source,
+                    execute,
+                    reason: error.to_string(),
+                },
+            ),
+        }
+    }
+
+    /// The config's default delivery, for actions that don't pick one.
+    fn default_delivery(handle: &PadRuntime) -> Delivery {
+        handle
+            .state()
+            .lock_config()
+            .map(|config| config.delivery)
+            .unwrap_or_default()
+    }
+
+    /// The chords that type `text` with the configured layout.
+    fn text_input(handle: &PadRuntime, text: &str) -> Input {
+        let chords = handle
+            .state()
+            .lock_config()
+            .map(|config| translate(text, config.keyboard_layout, config.text_fallback))
+            .unwrap_or_default();
+        Input::Sequence(chords)
+    }
+
+    /// Delivers `input` for an action, reporting it if that fails. Returns whether it went out.
+    fn send_input(
+        handle: &PadRuntime,
+        device: &str,
+        mode: &str,
+        source: &ActionSource,
+        delivery: Delivery,
+        input: Input,
+    ) -> bool {
+        match deliver(handle, device, delivery, input) {
+            Ok(()) => true,
+            Err(reason) => {
+                publish_action_event(
+                    handle.clone(),
+                    ActionEvent::InputFailed {
+                        mode: mode.to_string(),
+                        source: source.clone(),
+                        reason,
+                    },
+                );
+                false
+            }
+        }
+    }
+
+    /// A device, mode and input a macro was started from.
+    type MacroSlot = (String, String, ActionSource);
+
+    /// Macros that can still be cancelled, with an id so a finished one only clears its own slot.
+    fn running_macros() -> &'static Mutex<HashMap<MacroSlot, (u64, AbortHandle)>> {
+        static RUNNING: OnceLock<Mutex<HashMap<MacroSlot, (u64, AbortHandle)>>> = OnceLock::new();
+        RUNNING.get_or_init(|| Mutex::new(HashMap::new()))
+    }
+
+    fn macro_queues() -> &'static Mutex<HashMap<MacroSlot, Arc<AsyncMutex<()>>>> {
+        static QUEUES: OnceLock<Mutex<HashMap<MacroSlot, Arc<AsyncMutex<()>>>>> = OnceLock::new();
+        QUEUES.get_or_init(|| Mutex::new(HashMap::new()))
+    }
+
+    /// Lets macros bound to encoder rotation run one detent at a time instead of interleaving.
+    fn macro_queue(slot: &MacroSlot) -> Arc<AsyncMutex<()>> {
+        match macro_queues().lock() {
+            Ok(mut queues) => queues.entry(slot.clone()).or_default().clone(),
+            Err(_) => Arc::new(AsyncMutex::new(())),
+        }
+    }
+
+    /// Drops a slot's queue once the last macro waiting on it is done with it.
+    fn release_queue(slot: &MacroSlot, queue: Arc<AsyncMutex<()>>) {
+        if let Ok(mut queues) = macro_queues().lock() {
+            // Every waiter holds a clone, taken with the map locked, besides the map's own.
+            let drained = queues
+                .get(slot)
+                .is_some_and(|q| Arc::ptr_eq(q, &queue) && Arc::strong_count(&queue) == 2);
+            if drained {
+                queues.remove(slot);
+            }
+        }
+    }
+
+    static NEXT_MACRO: AtomicU64 = AtomicU64::new(0);
+
+    /// Runs macro steps in order, stopping at the first input that can't be delivered.
+    fn run_steps<'a>(
+        handle: &'a PadRuntime,
+        device: &'a str,
+        mode: &'a str,
+        source: &'a ActionSource,
+        delivery: Delivery,
+        steps: Vec<MacroStep>,
+    ) -> Pin<Box<dyn Future<Output = bool> + Send + 'a>> {
+        Box::pin(async move {
+            for step in steps {
+                let (input, delivery) = match step {
+                    MacroStep::Chord { keys } => (Input::Chord(keys), delivery),
+                    MacroStep::KeyDown { keys } => (Input::Press(keys), delivery),
+                    MacroStep::KeyUp { keys } => (Input::Release(Some(keys)), delivery),
+                    MacroStep::Text { text } => (text_input(handle, &text), delivery),
+                    MacroStep::MouseMove { x, y } => (Input::MouseMove { x, y }, delivery),
+                    MacroStep::Scroll { amount } => (Input::Scroll(amount), delivery),
+                    MacroStep::Click { button } => (Input::Click(button), delivery),
+                    MacroStep::Delay { ms } => {
+                        sleep(Duration::from_millis(ms)).await;
+                        continue;
+                    }
+                    MacroStep::Action { action } => match *action {
+                        KeyAction::Command {
+                            execute,
+                            args,
+                            cwd,
+                            env,
+                            timeout: limit,
+                        } => {
+                            let command = build_command(execute.clone(), args, cwd, env);
+                            run_command(
+                                handle.clone(),
+                                mode.to_string(),
+                                source.clone(),
+                                execute,
+                                command,
+                                limit,
+                            )
+                            .await;
+                            continue;
+                        }
+                        KeyAction::KeyPress { key, delivery: own } => {
+                            (Input::Chord(key), own.unwrap_or(delivery))
+                        }
+                        KeyAction::Macro {
+                            steps,
+                            delivery: own,
+                        } => {
+                            if run_steps(
+                                handle,
+                                device,
+                                mode,
+                                source,
+                                own.unwrap_or(delivery),
+                                steps,
+                            )
+                            .await
+                            {
+                                continue;
+                            }
+                            return false;
+                        }
+                        KeyAction::TypeText {
+                            text,
+                            delivery: own,
+                        } => (text_input(handle, &text), own.unwrap_or(delivery)),
+                        KeyAction::None {} => continue,
+                    },
+                };
+
+                if !send_input(handle, device, mode, source, delivery, input) {
+                    return false;
+                }
+            }
+            true
+        })
+    }
+
+    /// Runs a macro on `device`, or cancels it if the same input already has it running. Anything
+    /// left held down is released when it ends either way.
+    fn start_macro(
+        handle: PadRuntime,
+        device: String,
+        mode: String,
+        source: ActionSource,
+        delivery: Delivery,
+        steps: Vec<MacroStep>,
+    ) {
+        let slot: MacroSlot = (device.clone(), mode.clone(), source.clone());
+        let rotation = matches!(
+            source,
+            ActionSource::Encoder {
+                encoder: EncoderInput::Clockwise
+                    | EncoderInput::CounterClockwise
+                    | EncoderInput::HeldClockwise
+                    | EncoderInput::HeldCounterClockwise
+            }
+        );
+
+        let Ok(mut running) = running_macros().lock() else {
+            return;
+        };
+        if !rotation {
+            if let Some((_, task)) = running.remove(&slot) {
+                task.abort();
+                let _ = deliver(&handle, &device, delivery, Input::Release(None));
+                publish_action_event(handle, ActionEvent::MacroCancelled { mode, source });
+                return;
+            }
+        }
+
+        let id = NEXT_MACRO.fetch_add(1, Ordering::Relaxed);
+        let queue = rotation.then(|| macro_queue(&slot));
+        let task = spawn({
+            let slot = slot.clone();
+            async move {
+                let turn = match &queue {
+                    Some(queue) => Some(queue.lock().await),
+                    None => None,
+                };
+                publish_action_event(
+                    handle.clone(),
+                    ActionEvent::MacroStarted {
+                        mode: mode.clone(),
+                        source: source.clone(),
+                    },
+                );
+                run_steps(&handle, &device, &mode, &source, delivery, steps).await;
+                let _ = deliver(&handle, &device, delivery, Input::Release(None));
+                publish_action_event(handle, ActionEvent::MacroFinished { mode, source });
+
+                drop(turn);
+                if let Some(queue) = queue {
+                    release_queue(&slot, queue);
+                }
+                if let Ok(mut running) = running_macros().lock() {
+                    if running
+                        .get(&slot)
+                        .is_some_and(|(current, _)| *current == id)
+                    {
+                        running.remove(&slot);
+                    }
+                }
+            }
+        });
+        if !rotation {
+            running.insert(slot, (id, task.abort_handle()));
+        }
+    }
+
+    pub fn execute_action(handle: PadRuntime, device: String, mode: String, index: usize) {
+        let action = handle
+            .state()
+            .lock_config()
+            .and_then(|config| config.resolve_action(mode.clone(), index));
+
+        run_action(handle, device, mode, ActionSource::Key { index }, action);
+    }
+
+    /// Runs a bound action on `device`. Chords bound to keys are typed by the pad's own HID
+    /// interface unless they are delivered through uinput, so those are only reported.
+    fn run_action(
+        handle: PadRuntime,
+        device: String,
+        mode: String,
+        source: ActionSource,
+        action: Option<KeyAction>,
+    ) {
+        let delivery = action
+            .as_ref()
+            .and_then(|action| action.delivery())
+            .unwrap_or_else(|| default_delivery(&handle));
+
+        match action {
+            Some(KeyAction::Command {
+                execute,
+                args,
+                cwd,
+                env,
+                timeout: limit,
+            }) => {
+                let command = build_command(execute.clone(), args, cwd, env);
+                spawn(run_command(handle, mode, source, execute, command, limit));
+            }
+            Some(KeyAction::KeyPress { key, .. }) => {
+                let typed_by_pad =
+                    matches!(source, ActionSource::Key { .. }) && delivery == Delivery::PadHid;
+                if typed_by_pad
+                    || send_input(
+                        &handle,
+                        &device,
+                        &mode,
+                        &source,
+                        delivery,
+                        Input::Chord(key.clone()),
+                    )
+                {
+                    publish_action_event(handle, ActionEvent::KeyPress { mode, source, key })
+                }
+            }
+            Some(KeyAction::Macro { steps, .. }) => {
+                start_macro(handle, device, mode, source, delivery, steps)
+            }
+            Some(KeyAction::TypeText { text, .. }) => {
+                let input = text_input(&handle, &text);
+                if send_input(&handle, &device, &mode, &source, delivery, input) {
+                    publish_action_event(handle, ActionEvent::Typed { mode, source, text })
+                }
+            }
+            Some(KeyAction::None {}) | None => {
+                publish_action_event(handle, ActionEvent::Unbound { mode, source })
+            }
+        }
+    }
+
+    /// Runs the binding for one encoder input, once per detent.
+    fn execute_encoder_action(
+        handle: &PadRuntime,
+        device: &str,
+        mode: &str,
+        input: EncoderInput,
+        times: u32,
+    ) {
+        let action = handle
+            .state()
+            .lock_config()
+            .and_then(|config| config.resolve_encoder_action(mode, input));
+
+        for _ in 0..times {
+            run_action(
+                handle.clone(),
+                device.to_string(),
+                mode.to_string(),
+                ActionSource::Encoder { encoder: input },
+                action.clone(),
+            );
+        }
+    }
+
+    /// Whether the knob of each device is held down, and if it was turned since.
+    type EncoderHolds = Arc<Mutex<HashMap<String, bool>>>;
+
+    fn handle_encoder(handle: &PadRuntime, holds: &EncoderHolds, device: String, event: PadEvent) {
+        let Ok(mut holds) = holds.lock() else {
+            return;
+        };
+
+        match event {
+            PadEvent::EncoderSwitch {
+                mode: Some(_),
+                pressed: true,
+            } => {
+                holds.insert(device, false);
+            }
+            PadEvent::EncoderSwitch {
+                mode: Some(mode),
+                pressed: false,
+            } if holds.remove(&device) == Some(false) => {
+                execute_encoder_action(handle, &device, &mode, EncoderInput::Press, 1);
+            }
+            PadEvent::EncoderRotated {
+                mode: Some(mode),
+                delta,
+                ..
+            } if delta != 0 => {
+                let held = holds
+                    .get_mut(&device)
+                    .map(|turned| *turned = true)
+                    .is_some();
+                let input = match (held, delta > 0) {
+                    (false, true) => EncoderInput::Clockwise,
+                    (false, false) => EncoderInput::CounterClockwise,
+                    (true, true) => EncoderInput::HeldClockwise,
+                    (true, false) => EncoderInput::HeldCounterClockwise,
+                };
+                execute_encoder_action(handle, &device, &mode, input, delta.unsigned_abs());
+            }
+            PadEvent::Disconnect | PadEvent::Connect | PadEvent::ModeChanged { .. } => {
+                holds.remove(&device);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn start_action_executor(runtime: &PadRuntime) {
+        let handle = runtime.clone();
+        let holds: EncoderHolds = Arc::new(Mutex::new(HashMap::new()));
+        runtime.listen("ratpad://serial", move |payload| {
+            let Some(Ok(SerialEvent::Event { device, event, .. })) =
+                payload.map(serde_json::from_str::<SerialEvent>)
+            else {
+                return;
+            };
+
+            match event {
+                PadEvent::KeyPressed {
+                    mode: Some(mode),
+                    key,
+                } => {
+                    if let Ok(index) = usize::try_from(key.action) {
+                        execute_action(handle.clone(), device, mode, index);
+                    }
+                }
+                event => handle_encoder(&handle, &holds, device, event),
+            }
+        });
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn queues_are_dropped_once_drained() {
+            let slot: MacroSlot = (
+                "pad".to_string(),
+                "mode".to_string(),
+                ActionSource::Key { index: 0 },
+            );
+            let first = macro_queue(&slot);
+            let second = macro_queue(&slot);
+            assert!(Arc::ptr_eq(&first, &second));
+
+            release_queue(&slot, first);
+            assert!(macro_queues().lock().unwrap().contains_key(&slot));
+            release_queue(&slot, second);
+            assert!(!macro_queues().lock().unwrap().contains_key(&slot));
+        }
+    }
+}
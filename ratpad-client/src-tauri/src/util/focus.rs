@@ -0,0 +1,382 @@
+pub mod focus_watcher {
+    use serde::{Deserialize, Serialize};
+
+    use crate::util::{
+        command_handler::{execute, CommandTypes},
+        pad_runtime::PadRuntime,
+    };
+
+    /// The desktop window that has input focus.
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+    pub struct FocusedWindow {
+        /// Both parts of WM_CLASS, instance first.
+        pub class: Vec<String>,
+        pub title: String,
+        pub process: Option<String>,
+    }
+
+    /// Sent on "ratpad://focus" whenever the focused window changes.
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct FocusEvent {
+        pub window: Option<FocusedWindow>,
+
+        /// The mode the window's rules picked, if any.
+        pub mode: Option<String>,
+
+        /// Whether the pads were switched, which only happens with auto switching on.
+        pub applied: bool,
+
+        /// Why the pads couldn't be switched.
+        pub error: Option<String>,
+    }
+
+    /// Picks a mode for the newly focused window and switches the pads to it. `last` is the mode
+    /// the watcher last switched to, so the pads are only touched when the choice changes and the
+    /// user can still page around by hand in between.
+    fn handle_focus(
+        handle: &PadRuntime,
+        window: Option<FocusedWindow>,
+        last: &mut Option<Option<String>>,
+    ) {
+        let Some((enabled, mode)) = handle.state().lock_config().map(|config| {
+            (
+                config.auto_switch,
+                window.as_ref().and_then(|w| config.mode_for_window(w)),
+            )
+        }) else {
+            return;
+        };
+
+        let mut applied = enabled && last.as_ref() != Some(&mode);
+        let mut error = None;
+        if applied {
+            let command = match mode.clone() {
+                Some(mode) => CommandTypes::PadSetMode { mode, device: None },
+                None => CommandTypes::PadSetHome { device: None },
+            };
+            // A switch that failed is tried again on the next change.
+            match execute(handle.clone(), command) {
+                Ok(_) => *last = Some(mode.clone()),
+                Err(reason) => {
+                    applied = false;
+                    error = Some(reason);
+                }
+            }
+        }
+
+        handle.emit(
+            "ratpad://focus",
+            FocusEvent {
+                window,
+                mode,
+                applied,
+                error,
+            },
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    mod platform {
+        use std::{error::Error, fs::read_to_string, thread};
+
+        use x11rb::{
+            connection::Connection,
+            protocol::{
+                xproto::{AtomEnum, ChangeWindowAttributesAux, ConnectionExt, EventMask, Window},
+                Event,
+            },
+            rust_connection::RustConnection,
+        };
+
+        use super::FocusedWindow;
+        use crate::util::pad_runtime::PadRuntime;
+
+        x11rb::atom_manager! {
+            Atoms: AtomsCookie {
+                _NET_ACTIVE_WINDOW,
+                _NET_WM_NAME,
+                _NET_WM_PID,
+                UTF8_STRING,
+            }
+        }
+
+        fn property(
+            conn: &RustConnection,
+            window: Window,
+            name: impl Into<u32>,
+            kind: impl Into<u32>,
+        ) -> Option<Vec<u8>> {
+            conn.get_property(false, window, name, kind, 0, 1024)
+                .ok()?
+                .reply()
+                .ok()
+                .map(|reply| reply.value)
+                .filter(|value| !value.is_empty())
+        }
+
+        fn active_window(conn: &RustConnection, root: Window, atoms: &Atoms) -> Option<Window> {
+            conn.get_property(
+                false,
+                root,
+                atoms._NET_ACTIVE_WINDOW,
+                AtomEnum::WINDOW,
+                0,
+                1,
+            )
+            .ok()?
+            .reply()
+            .ok()?
+            .value32()
+            .and_then(first_window)
+        }
+
+        /// The window in a `_NET_ACTIVE_WINDOW` value. The window manager sets it to 0 when nothing
+        /// has focus.
+        pub(super) fn first_window(mut value: impl Iterator<Item = u32>) -> Option<Window> {
+            value.next().filter(|window| *window != 0)
+        }
+
+        /// Splits a WM_CLASS value, which holds the instance and class names each ended by a null.
+        pub(super) fn parse_class(value: &[u8]) -> Vec<String> {
+            value
+                .split(|b| *b == 0)
+                .filter(|part| !part.is_empty())
+                .map(|part| String::from_utf8_lossy(part).to_string())
+                .collect()
+        }
+
+        /// The name of a running process, from its `/proc` entry.
+        pub(super) fn process_name(pid: u32) -> Option<String> {
+            read_to_string(format!("/proc/{pid}/comm"))
+                .ok()
+                .map(|name| name.trim().to_string())
+        }
+
+        fn read_window(conn: &RustConnection, atoms: &Atoms, window: Window) -> FocusedWindow {
+            let class = property(conn, window, AtomEnum::WM_CLASS, AtomEnum::STRING)
+                .map(|value| parse_class(&value))
+                .unwrap_or_default();
+
+            let title = property(conn, window, atoms._NET_WM_NAME, atoms.UTF8_STRING)
+                .or_else(|| property(conn, window, AtomEnum::WM_NAME, AtomEnum::STRING))
+                .map(|value| String::from_utf8_lossy(&value).to_string())
+                .unwrap_or_default();
+
+            let process = conn
+                .get_property(false, window, atoms._NET_WM_PID, AtomEnum::CARDINAL, 0, 1)
+                .ok()
+                .and_then(|cookie| cookie.reply().ok())
+                .and_then(|reply| reply.value32()?.next())
+                .and_then(process_name);
+
+            FocusedWindow {
+                class,
+                title,
+                process,
+            }
+        }
+
+        fn listen_to(conn: &RustConnection, window: Window, mask: EventMask) {
+            let _ = conn.change_window_attributes(
+                window,
+                &ChangeWindowAttributesAux::new().event_mask(mask),
+            );
+        }
+
+        fn watch(handle: PadRuntime) -> Result<(), Box<dyn Error>> {
+            let (conn, screen) = x11rb::connect(None)?;
+            let root = conn.setup().roots[screen].root;
+            let atoms = Atoms::new(&conn)?.reply()?;
+
+            listen_to(&conn, root, EventMask::PROPERTY_CHANGE);
+            conn.flush()?;
+
+            let mut watched: Option<Window> = None;
+            let mut current: Option<Option<FocusedWindow>> = None;
+            let mut last = None;
+
+            loop {
+                // Follow the focused window itself too, so title changes (browser tabs, open
+                // documents) are picked up without a focus change.
+                let window = active_window(&conn, root, &atoms);
+                if window != watched {
+                    if let Some(old) = watched {
+                        listen_to(&conn, old, EventMask::NO_EVENT);
+                    }
+                    if let Some(new) = window {
+                        listen_to(&conn, new, EventMask::PROPERTY_CHANGE);
+                    }
+                    conn.flush()?;
+                    watched = window;
+                }
+
+                let focused = window.map(|w| read_window(&conn, &atoms, w));
+                if current.as_ref() != Some(&focused) {
+                    super::handle_focus(&handle, focused.clone(), &mut last);
+                    current = Some(focused);
+                }
+
+                loop {
+                    if let Event::PropertyNotify(event) = conn.wait_for_event()? {
+                        let focus_moved =
+                            event.window == root && event.atom == atoms._NET_ACTIVE_WINDOW;
+                        let title_changed = Some(event.window) == watched
+                            && (event.atom == atoms._NET_WM_NAME
+                                || event.atom == u32::from(AtomEnum::WM_NAME));
+                        if focus_moved || title_changed {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        pub fn start(handle: PadRuntime) {
+            thread::spawn(move || {
+                if let Err(error) = watch(handle.clone()) {
+                    handle.report_failure("focus_watcher", error);
+                }
+            });
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    mod platform {
+        use crate::util::pad_runtime::PadRuntime;
+
+        pub fn start(_handle: PadRuntime) {}
+    }
+
+    /// Watches which window has focus and switches the pads to the mode its rules pick, when
+    /// auto switching is on. Needs an X11 (or XWayland) display, taken from `DISPLAY`.
+    pub fn start_focus_watcher(runtime: &PadRuntime) {
+        platform::start(runtime.clone());
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::{
+            env::temp_dir,
+            process,
+            sync::{Arc, Mutex},
+        };
+
+        use serde_json::{json, Value};
+
+        use super::*;
+        use crate::util::{app_state::ConnectionState, configuration::AppConfig};
+
+        #[cfg(target_os = "linux")]
+        use super::platform::{first_window, parse_class, process_name};
+
+        type Shared<T> = Arc<Mutex<T>>;
+
+        fn window(class: &str) -> Option<FocusedWindow> {
+            Some(FocusedWindow {
+                class: vec![class.to_string()],
+                ..FocusedWindow::default()
+            })
+        }
+
+        /// A runtime with one connected pad, the focus events it emits and the mode switches it
+        /// sends to the pad.
+        fn runtime(auto_switch: bool) -> (PadRuntime, Shared<Vec<Value>>, Shared<usize>) {
+            let runtime =
+                PadRuntime::new(temp_dir().join(format!("ratpad-focus-{}", process::id())));
+            *runtime.state().lock_config().unwrap() = AppConfig::from_document(json!({
+                "colors": {
+                    "next": [0, 0, 0],
+                    "previous": [0, 0, 0],
+                    "select": [0, 0, 0],
+                    "brightness": 1.0,
+                },
+                "auto_switch": auto_switch,
+                "modes": [{
+                    "key": "code", "title": "Code", "title_short": "C", "color": null, "keys": [],
+                    "focus": [{"class": "code"}],
+                }],
+            }))
+            .unwrap();
+            runtime
+                .state()
+                .set("pad".to_string(), ConnectionState::Connected, None, None);
+
+            let events = Arc::new(Mutex::new(Vec::new()));
+            let emitted = events.clone();
+            runtime.set_emitter(move |event, payload| {
+                if event == "ratpad://focus" {
+                    emitted.lock().unwrap().push(payload);
+                }
+            });
+            let switches = Arc::new(Mutex::new(0));
+            let sent = switches.clone();
+            runtime.listen("ratpad://serial/cmd", move |_| *sent.lock().unwrap() += 1);
+            (runtime, events, switches)
+        }
+
+        #[test]
+        fn switches_only_when_the_choice_changes() {
+            let (runtime, events, switches) = runtime(true);
+            let mut last = None;
+
+            handle_focus(&runtime, window("code"), &mut last);
+            handle_focus(&runtime, window("code"), &mut last);
+            handle_focus(&runtime, window("kitty"), &mut last);
+
+            let events = events.lock().unwrap();
+            let picked: Vec<_> = events.iter().map(|e| (&e["mode"], &e["applied"])).collect();
+            assert_eq!(
+                picked,
+                [
+                    (&json!("code"), &json!(true)),
+                    (&json!("code"), &json!(false)),
+                    (&Value::Null, &json!(true)),
+                ]
+            );
+            assert_eq!(*switches.lock().unwrap(), 2);
+            assert_eq!(last, Some(None));
+        }
+
+        #[test]
+        fn reports_without_switching_when_auto_switch_is_off() {
+            let (runtime, events, switches) = runtime(false);
+            let mut last = None;
+
+            handle_focus(&runtime, window("code"), &mut last);
+
+            let events = events.lock().unwrap();
+            assert_eq!(events.len(), 1);
+            assert_eq!(events[0]["mode"], "code");
+            assert_eq!(events[0]["applied"], false);
+            assert_eq!(*switches.lock().unwrap(), 0);
+            assert!(last.is_none());
+        }
+
+        #[cfg(target_os = "linux")]
+        #[test]
+        fn reads_the_active_window() {
+            assert_eq!(first_window([0x1e00007].into_iter()), Some(0x1e00007));
+            assert_eq!(first_window([0].into_iter()), None);
+            assert_eq!(first_window([].into_iter()), None);
+        }
+
+        #[cfg(target_os = "linux")]
+        #[test]
+        fn splits_wm_class() {
+            assert_eq!(
+                parse_class(b"navigator\0firefox\0"),
+                ["navigator", "firefox"]
+            );
+            assert_eq!(parse_class(b"kitty\0"), ["kitty"]);
+            assert!(parse_class(b"\0\0").is_empty());
+        }
+
+        #[cfg(target_os = "linux")]
+        #[test]
+        fn names_processes_from_proc() {
+            let own = std::fs::read_to_string("/proc/self/comm").unwrap();
+            assert_eq!(process_name(process::id()), Some(own.trim().to_string()));
+            assert_eq!(process_name(0), None);
+        }
+    }
+}
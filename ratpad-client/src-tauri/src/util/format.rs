@@ -0,0 +1,314 @@
+pub mod config_format {
+    use std::{
+        mem::take,
+        path::{Path, PathBuf},
+    };
+
+    use serde::{de::IntoDeserializer, Deserialize, Serialize};
+    use serde_json::{Map, Value};
+    use toml_edit::{ArrayOfTables, DocumentMut, Item, Table};
+
+    /// The file formats the config can be kept in. JSON is what the client writes unless told
+    /// otherwise; TOML and YAML are easier to edit by hand and to read in diffs.
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+    pub enum ConfigFormat {
+        #[serde(rename = "json")]
+        Json,
+
+        /// Comments and layout are kept when the client rewrites a file.
+        #[serde(rename = "toml")]
+        Toml,
+
+        /// Comments are lost when the client rewrites a file.
+        #[serde(rename = "yaml")]
+        Yaml,
+    }
+
+    /// The config file names looked for, in order, when more than one is present.
+    pub const CONFIG_FILES: [&str; 4] = ["config.toml", "config.yaml", "config.yml", "config.json"];
+
+    /// The config file in `dir`, `config.json` if there isn't one yet.
+    pub fn config_path(dir: &Path) -> PathBuf {
+        CONFIG_FILES
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.exists())
+            .unwrap_or_else(|| dir.join("config.json"))
+    }
+
+    impl ConfigFormat {
+        /// The format a file is in, from its extension.
+        pub fn from_path(path: &Path) -> Option<ConfigFormat> {
+            match path.extension()?.to_str()? {
+                "json" => Some(ConfigFormat::Json),
+                "toml" => Some(ConfigFormat::Toml),
+                "yaml" | "yml" => Some(ConfigFormat::Yaml),
+                _ => None,
+            }
+        }
+
+        pub fn extension(&self) -> &'static str {
+            match self {
+                ConfigFormat::Json => "json",
+                ConfigFormat::Toml => "toml",
+                ConfigFormat::Yaml => "yaml",
+            }
+        }
+
+        pub fn parse(&self, text: &str) -> Result<Value, String> {
+            match self {
+                ConfigFormat::Json => serde_json::from_str(text).map_err(|e| e.to_string()),
+                ConfigFormat::Toml => toml_edit::de::from_str::<Value>(text)
+                    .map(restore_nulls)
+                    .map_err(|e| e.to_string()),
+                ConfigFormat::Yaml => serde_yaml::from_str(text).map_err(|e| e.to_string()),
+            }
+        }
+
+        /// Writes a whole config document. `previous` is the file being replaced, whose comments
+        /// and layout are carried over where the format allows.
+        pub fn render(&self, document: &Value, previous: Option<&str>) -> Result<String, String> {
+            self.render_at(document, previous, 0)
+        }
+
+        /// Writes a single mode, for a file pulled in by `include`. It is laid out the way it
+        /// would be under `[[modes]]`.
+        pub fn render_mode(&self, mode: &Value, previous: Option<&str>) -> Result<String, String> {
+            self.render_at(mode, previous, 1)
+        }
+
+        fn render_at(
+            &self,
+            document: &Value,
+            previous: Option<&str>,
+            depth: usize,
+        ) -> Result<String, String> {
+            match self {
+                ConfigFormat::Json => serde_json::to_string(document).map_err(|e| e.to_string()),
+                ConfigFormat::Toml => {
+                    let mut rendered = toml_edit::ser::to_document(&strip_nulls(document.clone()))
+                        .map_err(|e| e.to_string())?;
+                    lay_out(rendered.as_table_mut(), depth);
+
+                    match previous.and_then(|text| text.parse::<DocumentMut>().ok()) {
+                        Some(mut kept) => {
+                            merge_table(kept.as_table_mut(), take(rendered.as_table_mut()));
+                            number_tables(kept.as_table_mut(), &mut 0);
+                            Ok(kept.to_string())
+                        }
+                        None => Ok(rendered.to_string()),
+                    }
+                }
+                ConfigFormat::Yaml => serde_yaml::to_string(document).map_err(|e| e.to_string()),
+            }
+        }
+    }
+
+    /// TOML has no null. Empty fields are left out, and empty slots in a list (keys without a
+    /// binding) become `{}`.
+    fn strip_nulls(value: Value) -> Value {
+        match value {
+            Value::Object(fields) => Value::Object(
+                fields
+                    .into_iter()
+                    .filter(|(_, v)| !v.is_null())
+                    .map(|(k, v)| (k, strip_nulls(v)))
+                    .collect(),
+            ),
+            Value::Array(items) => Value::Array(
+                items
+                    .into_iter()
+                    .map(|v| {
+                        if v.is_null() {
+                            Value::Object(Map::new())
+                        } else {
+                            strip_nulls(v)
+                        }
+                    })
+                    .collect(),
+            ),
+            other => other,
+        }
+    }
+
+    /// Undoes [`strip_nulls`] for the `keys` lists, the only lists with empty slots. Elsewhere
+    /// `{}` is a real entry, like a focus rule with nothing set. Missing fields need nothing, as
+    /// they default to `None`.
+    fn restore_nulls(value: Value) -> Value {
+        match value {
+            Value::Object(fields) => Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(k, v)| match v {
+                        Value::Array(items) if k == "keys" => {
+                            let items = items.into_iter().map(|v| match v {
+                                Value::Object(fields) if fields.is_empty() => Value::Null,
+                                other => restore_nulls(other),
+                            });
+                            (k, Value::Array(items.collect()))
+                        }
+                        other => (k, restore_nulls(other)),
+                    })
+                    .collect(),
+            ),
+            Value::Array(items) => Value::Array(items.into_iter().map(restore_nulls).collect()),
+            other => other,
+        }
+    }
+
+    /// Turns the serializer's inline tables into `[sections]` two levels deep, and the top-level
+    /// lists of tables (modes, devices) into `[[sections]]`. Anything deeper stays inline, with
+    /// lists of tables spread one entry per line.
+    fn lay_out(table: &mut Table, depth: usize) {
+        for (_, item) in table.iter_mut() {
+            *item = match take(item) {
+                Item::Value(toml_edit::Value::InlineTable(inline)) if depth < 2 => {
+                    let mut section = inline.into_table();
+                    lay_out(&mut section, depth + 1);
+                    Item::Table(section)
+                }
+                Item::Value(toml_edit::Value::Array(array))
+                    if depth == 0
+                        && !array.is_empty()
+                        && array
+                            .iter()
+                            .all(|v| v.as_inline_table().is_some_and(|t| !t.is_empty())) =>
+                {
+                    let mut sections = ArrayOfTables::new();
+                    for value in array {
+                        if let toml_edit::Value::InlineTable(inline) = value {
+                            let mut section = inline.into_table();
+                            lay_out(&mut section, depth + 1);
+                            sections.push(section);
+                        }
+                    }
+                    Item::ArrayOfTables(sections)
+                }
+                Item::Value(toml_edit::Value::Array(mut array))
+                    if array.len() > 1 && array.iter().any(|v| v.is_inline_table()) =>
+                {
+                    for value in array.iter_mut() {
+                        value.decor_mut().set_prefix("\n    ");
+                    }
+                    array.set_trailing("\n");
+                    array.set_trailing_comma(true);
+                    Item::Value(toml_edit::Value::Array(array))
+                }
+                other => other,
+            };
+        }
+    }
+
+    fn as_json(item: &Item) -> Option<Value> {
+        let value = item.clone().into_value().ok()?;
+        Value::deserialize(value.into_deserializer()).ok()
+    }
+
+    /// Copies `new` into `old`, touching only what changed so the comments and formatting
+    /// around everything else stay put.
+    fn merge_table(old: &mut Table, new: Table) {
+        old.retain(|key, _| new.contains_key(key));
+        for (key, item) in new {
+            match old.get_mut(&key) {
+                Some(existing) => merge_item(existing, item),
+                None => {
+                    old.insert(&key, item);
+                }
+            }
+        }
+    }
+
+    fn merge_item(old: &mut Item, new: Item) {
+        if as_json(old).is_some() && as_json(old) == as_json(&new) {
+            return;
+        }
+        match (old, new) {
+            (Item::Table(old), Item::Table(new)) => merge_table(old, new),
+            (Item::ArrayOfTables(old), Item::ArrayOfTables(new)) => merge_sections(old, new),
+            (Item::Value(old), Item::Value(mut new)) => {
+                *new.decor_mut() = old.decor().clone();
+                *old = new;
+            }
+            (old, new) => *old = new,
+        }
+    }
+
+    /// Matches `[[sections]]` up by their `key` field, so a mode keeps its comments when the
+    /// modes around it are added, removed or moved.
+    fn merge_sections(old: &mut ArrayOfTables, new: ArrayOfTables) {
+        let key = |table: &Table| table.get("key").and_then(Item::as_str).map(str::to_string);
+        let mut merged = ArrayOfTables::new();
+        for (index, table) in new.into_iter().enumerate() {
+            let found = match key(&table) {
+                Some(name) => old.iter().find(|t| key(t).as_ref() == Some(&name)),
+                None => old.get(index),
+            };
+            match found.cloned() {
+                Some(mut kept) => {
+                    merge_table(&mut kept, table);
+                    merged.push(kept);
+                }
+                None => merged.push(table),
+            }
+        }
+        *old = merged;
+    }
+
+    /// Sections are written in the order of their positions. Renumbering them in document order
+    /// puts moved and new ones where they now belong.
+    fn number_tables(table: &mut Table, next: &mut usize) {
+        table.set_position(*next);
+        *next += 1;
+        for (_, item) in table.iter_mut() {
+            match item {
+                Item::Table(section) => number_tables(section, next),
+                Item::ArrayOfTables(sections) => {
+                    for section in sections.iter_mut() {
+                        number_tables(section, next);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use serde_json::json;
+
+        use super::*;
+        use crate::util::configuration::AppConfig;
+
+        #[test]
+        fn round_trips_empty_slots_and_empty_tables() {
+            let config = AppConfig::from_document(json!({
+                "colors": {
+                    "next": [0, 0, 0],
+                    "previous": [0, 0, 0],
+                    "select": [0, 0, 0],
+                    "brightness": 1.0,
+                },
+                "modes": [{
+                    "key": "m1",
+                    "title": "Mode",
+                    "title_short": "M",
+                    "color": null,
+                    "keys": [null, null, null],
+                    "focus": [{}, {"class": "firefox"}],
+                }],
+            }))
+            .unwrap();
+            let document = serde_json::to_value(&config).unwrap();
+
+            for format in [ConfigFormat::Json, ConfigFormat::Toml, ConfigFormat::Yaml] {
+                let text = format.render(&document, None).unwrap();
+                let read = AppConfig::from_document(format.parse(&text).unwrap()).unwrap();
+                assert_eq!(
+                    serde_json::to_value(&read).unwrap(),
+                    document,
+                    "{format:?}:\n{text}"
+                );
+            }
+        }
+    }
+}
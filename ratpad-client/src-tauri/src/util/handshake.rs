@@ -0,0 +1,80 @@
+pub mod pad_handshake {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+    use tokio::task::spawn_blocking;
+
+    use crate::{
+        ratpad_communication::{
+            CommandType, EventType, Message, MessageType, PadCapabilities, PROTOCOL_VERSION,
+        },
+        serial_client::SerialEvent,
+        util::{command_handler::publish_and_wait, pad_runtime::PadRuntime},
+    };
+
+    const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(2);
+
+    /// Sent on "ratpad://capabilities", to the front end and the internal bus, once a pad's
+    /// capabilities are stored. Anything that talks to the pad on connect waits for it.
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct CapabilitiesEvent {
+        pub device: String,
+        pub capabilities: PadCapabilities,
+    }
+
+    /// Sends `hello` and records what the pad answers. Firmware that never answers is treated as legacy.
+    pub fn perform_handshake(handle: PadRuntime, device: String) -> PadCapabilities {
+        let capabilities = publish_and_wait(
+            handle.clone(),
+            Some(device.clone()),
+            Message {
+                message_type: MessageType::Command(CommandType::Hello),
+                data: Some(json!({
+                    "protocol": PROTOCOL_VERSION,
+                    "client": env!("CARGO_PKG_VERSION"),
+                })),
+                id: None,
+            },
+            MessageType::Event(EventType::Capabilities),
+            Some(HANDSHAKE_TIMEOUT),
+        )
+        .ok()
+        .and_then(|reply| reply.data)
+        .and_then(|data| serde_json::from_value::<PadCapabilities>(data).ok())
+        .unwrap_or_else(PadCapabilities::legacy);
+
+        handle
+            .state()
+            .set_capabilities(device.clone(), Some(capabilities.clone()));
+        let event = CapabilitiesEvent {
+            device,
+            capabilities: capabilities.clone(),
+        };
+        if let Ok(serialized) = serde_json::to_string(&event) {
+            handle.trigger("ratpad://capabilities", Some(serialized));
+        }
+        handle.emit("ratpad://capabilities", event);
+        capabilities
+    }
+
+    pub fn start_handshake_listener(runtime: &PadRuntime) {
+        let handle = runtime.clone();
+        runtime.listen("ratpad://serial", move |payload| {
+            if let Some(payload) = payload {
+                match serde_json::from_str::<SerialEvent>(payload) {
+                    // Runs off the listener thread, since the reply arrives through it. Only the
+                    // transport's connect starts it, so each connection sends one `hello`.
+                    Ok(SerialEvent::Connect { device }) => {
+                        let handshake_handle = handle.clone();
+                        spawn_blocking(move || perform_handshake(handshake_handle, device));
+                    }
+                    Ok(SerialEvent::Disconnect { device }) => {
+                        handle.state().set_capabilities(device, None)
+                    }
+                    _ => (),
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,242 @@
+pub mod device_watcher {
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        serial_client::{send_listener_command, DeviceIdentity, ListenerCommand},
+        util::pad_runtime::PadRuntime,
+    };
+
+    /// Adafruit's USB vendor id.
+    pub const MACROPAD_VID: u16 = 0x239a;
+
+    /// MacroPad RP2040 product ids (Arduino and CircuitPython firmware).
+    pub const MACROPAD_PIDS: [u16; 2] = [0x8107, 0x8108];
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    #[serde(tag = "type")]
+    pub enum HotplugEvent {
+        #[serde(rename = "hotplug.arrived")]
+        Arrived {
+            port: String,
+            identity: DeviceIdentity,
+            device: Option<String>,
+        },
+
+        #[serde(rename = "hotplug.removed")]
+        Removed {
+            port: String,
+            identity: Option<DeviceIdentity>,
+            device: Option<String>,
+        },
+    }
+
+    pub fn is_macropad(identity: &DeviceIdentity) -> bool {
+        identity.vid == MACROPAD_VID && MACROPAD_PIDS.contains(&identity.pid)
+    }
+
+    fn saved_device(handle: &PadRuntime, identity: &DeviceIdentity) -> Option<String> {
+        handle
+            .state()
+            .lock_config()
+            .and_then(|config| config.device_by_identity(identity))
+            .map(|d| d.device)
+    }
+
+    fn handle_arrival(handle: PadRuntime, port: String, identity: DeviceIdentity) {
+        let saved = handle
+            .state()
+            .lock_config()
+            .and_then(|config| config.device_by_identity(&identity));
+
+        handle.emit(
+            "ratpad://hotplug",
+            HotplugEvent::Arrived {
+                port: port.clone(),
+                identity,
+                device: saved.clone().map(|d| d.device),
+            },
+        );
+
+        if let Some(device) = saved {
+            if let Some(rate) = device.rate {
+                send_listener_command(
+                    handle,
+                    ListenerCommand::Connect {
+                        device: device.device,
+                        new_port: port,
+                        new_rate: rate,
+                    },
+                );
+            }
+        }
+    }
+
+    fn handle_removal(handle: PadRuntime, port: String, identity: Option<DeviceIdentity>) {
+        let device = identity.as_ref().and_then(|i| saved_device(&handle, i));
+        handle.emit(
+            "ratpad://hotplug",
+            HotplugEvent::Removed {
+                port,
+                identity,
+                device,
+            },
+        );
+    }
+
+    /// Connects every saved device, preferring the port its identity is currently plugged into.
+    pub fn connect_saved_devices(runtime: &PadRuntime) {
+        let devices = match runtime.state().lock_config() {
+            Some(config) => config.devices.clone(),
+            None => return,
+        };
+
+        for device in devices {
+            let port = device
+                .identity
+                .as_ref()
+                .and_then(find_port)
+                .or(device.port.clone());
+            if let (Some(dev_port), Some(dev_rate)) = (port, device.rate) {
+                send_listener_command(
+                    runtime.clone(),
+                    ListenerCommand::Connect {
+                        device: device.device,
+                        new_port: dev_port,
+                        new_rate: dev_rate,
+                    },
+                );
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    mod platform {
+        use std::{path::Path, thread};
+
+        use tokio::{io::unix::AsyncFd, runtime::Handle};
+        use udev::{Device, Enumerator, EventType, MonitorBuilder};
+
+        use crate::{serial_client::DeviceIdentity, util::pad_runtime::PadRuntime};
+
+        fn property(device: &Device, name: &str) -> Option<String> {
+            device
+                .property_value(name)
+                .and_then(|v| v.to_str())
+                .map(|v| v.to_string())
+        }
+
+        fn identity(device: &Device) -> Option<DeviceIdentity> {
+            Some(DeviceIdentity {
+                vid: u16::from_str_radix(&property(device, "ID_VENDOR_ID")?, 16).ok()?,
+                pid: u16::from_str_radix(&property(device, "ID_MODEL_ID")?, 16).ok()?,
+                serial_number: property(device, "ID_SERIAL_SHORT"),
+                interface: property(device, "ID_USB_INTERFACE_NUM"),
+            })
+        }
+
+        fn tty_devices() -> Vec<(String, DeviceIdentity)> {
+            let mut enumerator = match Enumerator::new() {
+                Ok(enumerator) => enumerator,
+                Err(_) => return Vec::new(),
+            };
+            if enumerator.match_subsystem("tty").is_err() {
+                return Vec::new();
+            }
+
+            match enumerator.scan_devices() {
+                Ok(devices) => devices
+                    .filter_map(|device| {
+                        let node = device.devnode()?.to_str()?.to_string();
+                        Some((node, identity(&device)?))
+                    })
+                    .collect(),
+                Err(_) => Vec::new(),
+            }
+        }
+
+        pub fn identify_port(port: &str) -> Option<DeviceIdentity> {
+            tty_devices()
+                .into_iter()
+                .find(|(node, _)| Path::new(node) == Path::new(port))
+                .map(|(_, identity)| identity)
+        }
+
+        pub fn find_port(identity: &DeviceIdentity) -> Option<String> {
+            tty_devices()
+                .into_iter()
+                .find(|(_, found)| identity.matches(found))
+                .map(|(node, _)| node)
+        }
+
+        async fn watch(handle: PadRuntime) -> std::io::Result<()> {
+            let socket = MonitorBuilder::new()?.match_subsystem("tty")?.listen()?;
+            let monitor = AsyncFd::new(socket)?;
+
+            loop {
+                let mut guard = monitor.readable().await?;
+                for event in guard.get_inner().iter() {
+                    let Some(node) = event
+                        .devnode()
+                        .and_then(|n| n.to_str())
+                        .map(|n| n.to_string())
+                    else {
+                        continue;
+                    };
+                    let found = identity(&event);
+
+                    match event.event_type() {
+                        EventType::Add => {
+                            if let Some(found) = found.filter(super::is_macropad) {
+                                super::handle_arrival(handle.clone(), node, found);
+                            }
+                        }
+                        EventType::Remove if found.as_ref().is_none_or(super::is_macropad) => {
+                            super::handle_removal(handle.clone(), node, found);
+                        }
+                        _ => (),
+                    }
+                }
+                guard.clear_ready();
+            }
+        }
+
+        pub fn start(handle: PadRuntime) {
+            // The udev monitor isn't Sync, so it gets a thread of its own rather than a task.
+            let tokio_runtime = Handle::current();
+            thread::spawn(move || {
+                if let Err(error) = tokio_runtime.block_on(watch(handle.clone())) {
+                    handle.report_failure("device_watcher", error);
+                }
+            });
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    mod platform {
+        use crate::{serial_client::DeviceIdentity, util::pad_runtime::PadRuntime};
+
+        pub fn identify_port(_port: &str) -> Option<DeviceIdentity> {
+            None
+        }
+
+        pub fn find_port(_identity: &DeviceIdentity) -> Option<String> {
+            None
+        }
+
+        pub fn start(_handle: PadRuntime) {}
+    }
+
+    /// The USB identity of the pad on `port`. Only available where udev is.
+    pub fn identify_port(port: &str) -> Option<DeviceIdentity> {
+        platform::identify_port(port)
+    }
+
+    /// The port a device with this identity is currently plugged into.
+    pub fn find_port(identity: &DeviceIdentity) -> Option<String> {
+        platform::find_port(identity)
+    }
+
+    pub fn start_device_watcher(runtime: &PadRuntime) {
+        platform::start(runtime.clone());
+    }
+}
@@ -0,0 +1,294 @@
+pub mod input_injection {
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    use crate::{
+        ratpad_communication::{CommandType, Message, MessageType},
+        serial_client::send_serial_command,
+        util::{
+            keyboard_layout::SEQUENCE_CHUNK, keycode_catalog::canonical, pad_runtime::PadRuntime,
+        },
+    };
+
+    /// Where the key presses an action makes come from.
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+    pub enum Delivery {
+        /// The pad types them over its own USB HID interface.
+        #[default]
+        #[serde(rename = "pad_hid")]
+        PadHid,
+
+        /// The client types them through a uinput virtual keyboard and mouse on this host.
+        #[serde(rename = "host_uinput")]
+        HostUinput,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+    pub enum MouseButton {
+        #[serde(rename = "left")]
+        Left,
+
+        #[serde(rename = "right")]
+        Right,
+
+        #[serde(rename = "middle")]
+        Middle,
+    }
+
+    /// One piece of input for an action to make. Keys are chord strings (`CONTROL+SHIFT+P`).
+    #[derive(Clone, Debug)]
+    pub enum Input {
+        Chord(String),
+        Press(String),
+
+        /// Releases these keys, or everything held when `None`.
+        Release(Option<String>),
+
+        Sequence(Vec<String>),
+        MouseMove {
+            x: i32,
+            y: i32,
+        },
+        Scroll(i32),
+        Click(MouseButton),
+    }
+
+    fn send_to_pad(
+        handle: &PadRuntime,
+        device: &str,
+        command: CommandType,
+        data: serde_json::Value,
+    ) {
+        send_serial_command(
+            handle.clone(),
+            Some(device.to_string()),
+            Message {
+                message_type: MessageType::Command(command),
+                data: Some(data),
+                id: None,
+            },
+        );
+    }
+
+    fn deliver_to_pad(handle: &PadRuntime, device: &str, input: Input) {
+        match input {
+            Input::Chord(keys) => send_to_pad(
+                handle,
+                device,
+                CommandType::SendKeys,
+                json!({"keys": canonical(&keys)}),
+            ),
+            Input::Press(keys) => send_to_pad(
+                handle,
+                device,
+                CommandType::SendKeys,
+                json!({"keys": canonical(&keys), "action": "press"}),
+            ),
+            Input::Release(keys) => send_to_pad(
+                handle,
+                device,
+                CommandType::SendKeys,
+                json!({"keys": keys.as_deref().map(canonical), "action": "release"}),
+            ),
+            Input::Sequence(chords) => {
+                for chunk in chords.chunks(SEQUENCE_CHUNK) {
+                    send_to_pad(
+                        handle,
+                        device,
+                        CommandType::SendSequence,
+                        json!({"chords": chunk}),
+                    );
+                }
+            }
+            Input::MouseMove { x, y } => send_to_pad(
+                handle,
+                device,
+                CommandType::SendMouse,
+                json!({"x": x, "y": y}),
+            ),
+            Input::Scroll(amount) => send_to_pad(
+                handle,
+                device,
+                CommandType::SendMouse,
+                json!({"wheel": amount}),
+            ),
+            Input::Click(button) => send_to_pad(
+                handle,
+                device,
+                CommandType::SendMouse,
+                json!({"click": button}),
+            ),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    mod platform {
+        use std::{
+            collections::HashSet,
+            sync::{Mutex, OnceLock},
+        };
+
+        use evdev::{
+            uinput::VirtualDevice, AttributeSet, EventType, InputEvent, KeyCode, RelativeAxisCode,
+        };
+
+        use super::{Input, MouseButton};
+        use crate::util::keycode_catalog::{self, KEYCODES};
+
+        /// The virtual device, and the keys it is holding down.
+        struct Injector {
+            device: VirtualDevice,
+            held: HashSet<u16>,
+        }
+
+        fn injector() -> &'static Mutex<Option<Injector>> {
+            static INJECTOR: OnceLock<Mutex<Option<Injector>>> = OnceLock::new();
+            INJECTOR.get_or_init(|| Mutex::new(None))
+        }
+
+        fn create() -> Result<Injector, String> {
+            let mut keys = AttributeSet::<KeyCode>::new();
+            for keycode in KEYCODES {
+                keys.insert(KeyCode::new(keycode.evdev));
+            }
+            for button in [KeyCode::BTN_LEFT, KeyCode::BTN_RIGHT, KeyCode::BTN_MIDDLE] {
+                keys.insert(button);
+            }
+
+            let mut axes = AttributeSet::<RelativeAxisCode>::new();
+            for axis in [
+                RelativeAxisCode::REL_X,
+                RelativeAxisCode::REL_Y,
+                RelativeAxisCode::REL_WHEEL,
+            ] {
+                axes.insert(axis);
+            }
+
+            let device = VirtualDevice::builder()
+                .and_then(|builder| builder.name("ratpad virtual input").with_keys(&keys))
+                .and_then(|builder| builder.with_relative_axes(&axes))
+                .and_then(|builder| builder.build())
+                .map_err(|e| format!("Unable to create uinput device: {e}"))?;
+
+            Ok(Injector {
+                device,
+                held: HashSet::new(),
+            })
+        }
+
+        fn parse_chord(keys: &str) -> Result<Vec<u16>, String> {
+            keycode_catalog::parse_chord(keys)
+                .map(|chord| chord.keys.iter().map(|k| k.evdev).collect())
+        }
+
+        impl Injector {
+            fn key(&mut self, code: u16, down: bool) -> Result<(), String> {
+                let event = InputEvent::new(EventType::KEY.0, code, down as i32);
+                self.device.emit(&[event]).map_err(|e| e.to_string())?;
+                if down {
+                    self.held.insert(code);
+                } else {
+                    self.held.remove(&code);
+                }
+                Ok(())
+            }
+
+            fn chord(&mut self, keys: &str) -> Result<(), String> {
+                let codes = parse_chord(keys)?;
+                for code in codes.iter() {
+                    self.key(*code, true)?;
+                }
+                for code in codes.iter().rev() {
+                    self.key(*code, false)?;
+                }
+                Ok(())
+            }
+
+            fn relative(&mut self, axis: RelativeAxisCode, value: i32) -> Result<(), String> {
+                let event = InputEvent::new(EventType::RELATIVE.0, axis.0, value);
+                self.device.emit(&[event]).map_err(|e| e.to_string())
+            }
+
+            fn run(&mut self, input: Input) -> Result<(), String> {
+                match input {
+                    Input::Chord(keys) => self.chord(&keys),
+                    Input::Press(keys) => {
+                        for code in parse_chord(&keys)? {
+                            self.key(code, true)?;
+                        }
+                        Ok(())
+                    }
+                    Input::Release(Some(keys)) => {
+                        for code in parse_chord(&keys)?.into_iter().rev() {
+                            self.key(code, false)?;
+                        }
+                        Ok(())
+                    }
+                    Input::Release(None) => {
+                        for code in self.held.clone() {
+                            self.key(code, false)?;
+                        }
+                        Ok(())
+                    }
+                    Input::Sequence(chords) => {
+                        for chord in chords.iter() {
+                            self.chord(chord)?;
+                        }
+                        Ok(())
+                    }
+                    Input::MouseMove { x, y } => {
+                        self.relative(RelativeAxisCode::REL_X, x)?;
+                        self.relative(RelativeAxisCode::REL_Y, y)
+                    }
+                    Input::Scroll(amount) => self.relative(RelativeAxisCode::REL_WHEEL, amount),
+                    Input::Click(button) => {
+                        let code = match button {
+                            MouseButton::Left => KeyCode::BTN_LEFT,
+                            MouseButton::Right => KeyCode::BTN_RIGHT,
+                            MouseButton::Middle => KeyCode::BTN_MIDDLE,
+                        };
+                        self.key(code.code(), true)?;
+                        self.key(code.code(), false)
+                    }
+                }
+            }
+        }
+
+        pub fn inject(input: Input) -> Result<(), String> {
+            let mut slot = injector().lock().or(Err("Failed to lock uinput device"))?;
+            if slot.is_none() {
+                *slot = Some(create()?);
+            }
+            match slot.as_mut() {
+                Some(injector) => injector.run(input),
+                None => Err("No uinput device".to_string()),
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    mod platform {
+        use super::Input;
+
+        pub fn inject(_input: Input) -> Result<(), String> {
+            Err("Host input injection needs Linux uinput".to_string())
+        }
+    }
+
+    /// Makes `input` happen, through `device`'s HID interface or a uinput device on this host.
+    /// The uinput device is created on first use and kept, so the desktop only sees it once.
+    pub fn deliver(
+        handle: &PadRuntime,
+        device: &str,
+        delivery: Delivery,
+        input: Input,
+    ) -> Result<(), String> {
+        match delivery {
+            Delivery::PadHid => {
+                deliver_to_pad(handle, device, input);
+                Ok(())
+            }
+            Delivery::HostUinput => platform::inject(input),
+        }
+    }
+}
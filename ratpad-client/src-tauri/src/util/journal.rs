@@ -0,0 +1,143 @@
+pub mod config_journal {
+    use std::collections::VecDeque;
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::util::configuration::{AppConfig, ConfigEdit};
+
+    /// Edits kept for undoing before the oldest are dropped.
+    pub const MAX_HISTORY: usize = 100;
+
+    /// One command's worth of edits, stored as the edits that reverse it.
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct JournalEntry {
+        /// The command that made the change, like `config.clear_modes`.
+        pub command: String,
+        pub edits: Vec<ConfigEdit>,
+    }
+
+    /// Undo and redo history for the config. Only lives as long as the client does.
+    #[derive(Default)]
+    pub struct ConfigJournal {
+        undo: VecDeque<JournalEntry>,
+        redo: Vec<JournalEntry>,
+    }
+
+    impl ConfigJournal {
+        /// Remembers how to undo a change that was just made. Anything undone before it can no
+        /// longer be redone.
+        pub fn record(&mut self, command: &str, edits: Vec<ConfigEdit>) {
+            if edits.is_empty() {
+                return;
+            }
+            self.undo.push_back(JournalEntry {
+                command: command.to_string(),
+                edits,
+            });
+            if self.undo.len() > MAX_HISTORY {
+                self.undo.pop_front();
+            }
+            self.redo.clear();
+        }
+
+        /// Forgets everything, for when the config was replaced in a way the history can't
+        /// account for, like a profile switch or an edit on disk.
+        pub fn clear(&mut self) {
+            self.undo.clear();
+            self.redo.clear();
+        }
+
+        /// Reverses the newest change, returning the command that made it.
+        pub fn undo(&mut self, config: &mut AppConfig) -> Option<String> {
+            let entry = self.undo.pop_back()?;
+            let edits = config.apply_all(entry.edits);
+            self.redo.push(JournalEntry {
+                command: entry.command.clone(),
+                edits,
+            });
+            Some(entry.command)
+        }
+
+        /// Makes the newest undone change again, returning the command that made it.
+        pub fn redo(&mut self, config: &mut AppConfig) -> Option<String> {
+            let entry = self.redo.pop()?;
+            let edits = config.apply_all(entry.edits);
+            self.undo.push_back(JournalEntry {
+                command: entry.command.clone(),
+                edits,
+            });
+            Some(entry.command)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::util::command_handler::SetColorType;
+
+        /// Sets the next color the way a command does, recording it under `command`.
+        fn set_next(
+            journal: &mut ConfigJournal,
+            config: &mut AppConfig,
+            command: &str,
+            color: u32,
+        ) {
+            let undo = config.apply_all(vec![ConfigEdit::SetColor {
+                color: SetColorType::Next {
+                    color: (color, 0, 0),
+                },
+            }]);
+            journal.record(command, undo);
+        }
+
+        #[test]
+        fn undoes_and_redoes_in_order() {
+            let mut journal = ConfigJournal::default();
+            let mut config = AppConfig::default();
+            set_next(&mut journal, &mut config, "first", 1);
+            set_next(&mut journal, &mut config, "second", 2);
+
+            assert_eq!(journal.undo(&mut config).as_deref(), Some("second"));
+            assert_eq!(config.colors.next, (1, 0, 0));
+            assert_eq!(journal.undo(&mut config).as_deref(), Some("first"));
+            assert_eq!(config.colors.next, (0, 0, 0));
+            assert_eq!(journal.undo(&mut config), None);
+
+            assert_eq!(journal.redo(&mut config).as_deref(), Some("first"));
+            assert_eq!(journal.redo(&mut config).as_deref(), Some("second"));
+            assert_eq!(config.colors.next, (2, 0, 0));
+            assert_eq!(journal.redo(&mut config), None);
+        }
+
+        #[test]
+        fn new_changes_drop_the_redo_history() {
+            let mut journal = ConfigJournal::default();
+            let mut config = AppConfig::default();
+            set_next(&mut journal, &mut config, "first", 1);
+            journal.undo(&mut config);
+            set_next(&mut journal, &mut config, "other", 3);
+
+            assert_eq!(journal.redo(&mut config), None);
+            journal.record("nothing", Vec::new());
+            assert_eq!(journal.undo(&mut config).as_deref(), Some("other"));
+            assert_eq!(journal.undo(&mut config), None);
+        }
+
+        #[test]
+        fn keeps_only_the_newest_history() {
+            let mut journal = ConfigJournal::default();
+            let mut config = AppConfig::default();
+            for i in 1..=MAX_HISTORY as u32 + 5 {
+                set_next(&mut journal, &mut config, &i.to_string(), i);
+            }
+
+            let mut undone = Vec::new();
+            while let Some(command) = journal.undo(&mut config) {
+                undone.push(command);
+            }
+            assert_eq!(undone.len(), MAX_HISTORY);
+            assert_eq!(undone.last().map(String::as_str), Some("6"));
+            assert_eq!(config.colors.next, (5, 0, 0));
+        }
+    }
+}
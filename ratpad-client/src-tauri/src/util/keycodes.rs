@@ -0,0 +1,261 @@
+pub mod keycode_catalog {
+    use std::fmt::{self, Display, Formatter};
+
+    /// A key the pad's CircuitPython `Keycode` class knows, with where it lands on a Linux host.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct Keycode {
+        pub name: &'static str,
+
+        /// USB HID usage id, the value of the `Keycode` attribute.
+        pub usage: u8,
+
+        /// The `KEY_*` code Linux maps the usage to.
+        pub evdev: u16,
+    }
+
+    /// Every `Keycode` attribute, by its canonical name.
+    #[rustfmt::skip]
+    pub const KEYCODES: &[Keycode] = &[
+        Keycode { name: "A", usage: 0x04, evdev: 30 },
+        Keycode { name: "B", usage: 0x05, evdev: 48 },
+        Keycode { name: "C", usage: 0x06, evdev: 46 },
+        Keycode { name: "D", usage: 0x07, evdev: 32 },
+        Keycode { name: "E", usage: 0x08, evdev: 18 },
+        Keycode { name: "F", usage: 0x09, evdev: 33 },
+        Keycode { name: "G", usage: 0x0a, evdev: 34 },
+        Keycode { name: "H", usage: 0x0b, evdev: 35 },
+        Keycode { name: "I", usage: 0x0c, evdev: 23 },
+        Keycode { name: "J", usage: 0x0d, evdev: 36 },
+        Keycode { name: "K", usage: 0x0e, evdev: 37 },
+        Keycode { name: "L", usage: 0x0f, evdev: 38 },
+        Keycode { name: "M", usage: 0x10, evdev: 50 },
+        Keycode { name: "N", usage: 0x11, evdev: 49 },
+        Keycode { name: "O", usage: 0x12, evdev: 24 },
+        Keycode { name: "P", usage: 0x13, evdev: 25 },
+        Keycode { name: "Q", usage: 0x14, evdev: 16 },
+        Keycode { name: "R", usage: 0x15, evdev: 19 },
+        Keycode { name: "S", usage: 0x16, evdev: 31 },
+        Keycode { name: "T", usage: 0x17, evdev: 20 },
+        Keycode { name: "U", usage: 0x18, evdev: 22 },
+        Keycode { name: "V", usage: 0x19, evdev: 47 },
+        Keycode { name: "W", usage: 0x1a, evdev: 17 },
+        Keycode { name: "X", usage: 0x1b, evdev: 45 },
+        Keycode { name: "Y", usage: 0x1c, evdev: 21 },
+        Keycode { name: "Z", usage: 0x1d, evdev: 44 },
+        Keycode { name: "ONE", usage: 0x1e, evdev: 2 },
+        Keycode { name: "TWO", usage: 0x1f, evdev: 3 },
+        Keycode { name: "THREE", usage: 0x20, evdev: 4 },
+        Keycode { name: "FOUR", usage: 0x21, evdev: 5 },
+        Keycode { name: "FIVE", usage: 0x22, evdev: 6 },
+        Keycode { name: "SIX", usage: 0x23, evdev: 7 },
+        Keycode { name: "SEVEN", usage: 0x24, evdev: 8 },
+        Keycode { name: "EIGHT", usage: 0x25, evdev: 9 },
+        Keycode { name: "NINE", usage: 0x26, evdev: 10 },
+        Keycode { name: "ZERO", usage: 0x27, evdev: 11 },
+        Keycode { name: "ENTER", usage: 0x28, evdev: 28 },
+        Keycode { name: "ESCAPE", usage: 0x29, evdev: 1 },
+        Keycode { name: "BACKSPACE", usage: 0x2a, evdev: 14 },
+        Keycode { name: "TAB", usage: 0x2b, evdev: 15 },
+        Keycode { name: "SPACEBAR", usage: 0x2c, evdev: 57 },
+        Keycode { name: "MINUS", usage: 0x2d, evdev: 12 },
+        Keycode { name: "EQUALS", usage: 0x2e, evdev: 13 },
+        Keycode { name: "LEFT_BRACKET", usage: 0x2f, evdev: 26 },
+        Keycode { name: "RIGHT_BRACKET", usage: 0x30, evdev: 27 },
+        Keycode { name: "BACKSLASH", usage: 0x31, evdev: 43 },
+        Keycode { name: "POUND", usage: 0x32, evdev: 43 },
+        Keycode { name: "SEMICOLON", usage: 0x33, evdev: 39 },
+        Keycode { name: "QUOTE", usage: 0x34, evdev: 40 },
+        Keycode { name: "GRAVE_ACCENT", usage: 0x35, evdev: 41 },
+        Keycode { name: "COMMA", usage: 0x36, evdev: 51 },
+        Keycode { name: "PERIOD", usage: 0x37, evdev: 52 },
+        Keycode { name: "FORWARD_SLASH", usage: 0x38, evdev: 53 },
+        Keycode { name: "CAPS_LOCK", usage: 0x39, evdev: 58 },
+        Keycode { name: "F1", usage: 0x3a, evdev: 59 },
+        Keycode { name: "F2", usage: 0x3b, evdev: 60 },
+        Keycode { name: "F3", usage: 0x3c, evdev: 61 },
+        Keycode { name: "F4", usage: 0x3d, evdev: 62 },
+        Keycode { name: "F5", usage: 0x3e, evdev: 63 },
+        Keycode { name: "F6", usage: 0x3f, evdev: 64 },
+        Keycode { name: "F7", usage: 0x40, evdev: 65 },
+        Keycode { name: "F8", usage: 0x41, evdev: 66 },
+        Keycode { name: "F9", usage: 0x42, evdev: 67 },
+        Keycode { name: "F10", usage: 0x43, evdev: 68 },
+        Keycode { name: "F11", usage: 0x44, evdev: 87 },
+        Keycode { name: "F12", usage: 0x45, evdev: 88 },
+        Keycode { name: "PRINT_SCREEN", usage: 0x46, evdev: 99 },
+        Keycode { name: "SCROLL_LOCK", usage: 0x47, evdev: 70 },
+        Keycode { name: "PAUSE", usage: 0x48, evdev: 119 },
+        Keycode { name: "INSERT", usage: 0x49, evdev: 110 },
+        Keycode { name: "HOME", usage: 0x4a, evdev: 102 },
+        Keycode { name: "PAGE_UP", usage: 0x4b, evdev: 104 },
+        Keycode { name: "DELETE", usage: 0x4c, evdev: 111 },
+        Keycode { name: "END", usage: 0x4d, evdev: 107 },
+        Keycode { name: "PAGE_DOWN", usage: 0x4e, evdev: 109 },
+        Keycode { name: "RIGHT_ARROW", usage: 0x4f, evdev: 106 },
+        Keycode { name: "LEFT_ARROW", usage: 0x50, evdev: 105 },
+        Keycode { name: "DOWN_ARROW", usage: 0x51, evdev: 108 },
+        Keycode { name: "UP_ARROW", usage: 0x52, evdev: 103 },
+        Keycode { name: "KEYPAD_NUMLOCK", usage: 0x53, evdev: 69 },
+        Keycode { name: "KEYPAD_FORWARD_SLASH", usage: 0x54, evdev: 98 },
+        Keycode { name: "KEYPAD_ASTERISK", usage: 0x55, evdev: 55 },
+        Keycode { name: "KEYPAD_MINUS", usage: 0x56, evdev: 74 },
+        Keycode { name: "KEYPAD_PLUS", usage: 0x57, evdev: 78 },
+        Keycode { name: "KEYPAD_ENTER", usage: 0x58, evdev: 96 },
+        Keycode { name: "KEYPAD_ONE", usage: 0x59, evdev: 79 },
+        Keycode { name: "KEYPAD_TWO", usage: 0x5a, evdev: 80 },
+        Keycode { name: "KEYPAD_THREE", usage: 0x5b, evdev: 81 },
+        Keycode { name: "KEYPAD_FOUR", usage: 0x5c, evdev: 75 },
+        Keycode { name: "KEYPAD_FIVE", usage: 0x5d, evdev: 76 },
+        Keycode { name: "KEYPAD_SIX", usage: 0x5e, evdev: 77 },
+        Keycode { name: "KEYPAD_SEVEN", usage: 0x5f, evdev: 71 },
+        Keycode { name: "KEYPAD_EIGHT", usage: 0x60, evdev: 72 },
+        Keycode { name: "KEYPAD_NINE", usage: 0x61, evdev: 73 },
+        Keycode { name: "KEYPAD_ZERO", usage: 0x62, evdev: 82 },
+        Keycode { name: "KEYPAD_PERIOD", usage: 0x63, evdev: 83 },
+        Keycode { name: "KEYPAD_BACKSLASH", usage: 0x64, evdev: 86 },
+        Keycode { name: "APPLICATION", usage: 0x65, evdev: 127 },
+        Keycode { name: "POWER", usage: 0x66, evdev: 116 },
+        Keycode { name: "KEYPAD_EQUALS", usage: 0x67, evdev: 117 },
+        Keycode { name: "F13", usage: 0x68, evdev: 183 },
+        Keycode { name: "F14", usage: 0x69, evdev: 184 },
+        Keycode { name: "F15", usage: 0x6a, evdev: 185 },
+        Keycode { name: "F16", usage: 0x6b, evdev: 186 },
+        Keycode { name: "F17", usage: 0x6c, evdev: 187 },
+        Keycode { name: "F18", usage: 0x6d, evdev: 188 },
+        Keycode { name: "F19", usage: 0x6e, evdev: 189 },
+        Keycode { name: "F20", usage: 0x6f, evdev: 190 },
+        Keycode { name: "F21", usage: 0x70, evdev: 191 },
+        Keycode { name: "F22", usage: 0x71, evdev: 192 },
+        Keycode { name: "F23", usage: 0x72, evdev: 193 },
+        Keycode { name: "F24", usage: 0x73, evdev: 194 },
+        Keycode { name: "LEFT_CONTROL", usage: 0xe0, evdev: 29 },
+        Keycode { name: "LEFT_SHIFT", usage: 0xe1, evdev: 42 },
+        Keycode { name: "LEFT_ALT", usage: 0xe2, evdev: 56 },
+        Keycode { name: "LEFT_GUI", usage: 0xe3, evdev: 125 },
+        Keycode { name: "RIGHT_CONTROL", usage: 0xe4, evdev: 97 },
+        Keycode { name: "RIGHT_SHIFT", usage: 0xe5, evdev: 54 },
+        Keycode { name: "RIGHT_ALT", usage: 0xe6, evdev: 100 },
+        Keycode { name: "RIGHT_GUI", usage: 0xe7, evdev: 126 },
+    ];
+
+    /// Other names `Keycode` accepts for the same key.
+    pub const ALIASES: &[(&str, &str)] = &[
+        ("RETURN", "ENTER"),
+        ("SPACE", "SPACEBAR"),
+        ("CONTROL", "LEFT_CONTROL"),
+        ("SHIFT", "LEFT_SHIFT"),
+        ("ALT", "LEFT_ALT"),
+        ("OPTION", "LEFT_ALT"),
+        ("GUI", "LEFT_GUI"),
+        ("WINDOWS", "LEFT_GUI"),
+        ("COMMAND", "LEFT_GUI"),
+    ];
+
+    /// Shorter names only the client understands. Chords are rewritten to canonical names before
+    /// they reach the pad, so these never hit `getattr(Keycode, ...)`.
+    pub const SHORTHANDS: &[(&str, &str)] = &[
+        ("CTRL", "LEFT_CONTROL"),
+        ("ESC", "ESCAPE"),
+        ("DEL", "DELETE"),
+        ("INS", "INSERT"),
+        ("PGUP", "PAGE_UP"),
+        ("PGDN", "PAGE_DOWN"),
+        ("CMD", "LEFT_GUI"),
+        ("WIN", "LEFT_GUI"),
+        ("SUPER", "LEFT_GUI"),
+        ("ALTGR", "RIGHT_ALT"),
+    ];
+
+    /// Most non-modifier keys a boot keyboard report can hold at once.
+    pub const MAX_CHORD_KEYS: usize = 6;
+
+    /// Looks a key up by name or alias. Case is ignored, as the firmware upper-cases names.
+    pub fn lookup(name: &str) -> Option<&'static Keycode> {
+        let name = name.trim().to_ascii_uppercase();
+        let canonical = ALIASES
+            .iter()
+            .chain(SHORTHANDS.iter())
+            .find(|(alias, _)| *alias == name)
+            .map_or(name.as_str(), |(_, target)| *target);
+        KEYCODES.iter().find(|k| k.name == canonical)
+    }
+
+    impl Keycode {
+        pub fn is_modifier(&self) -> bool {
+            self.usage >= 0xe0
+        }
+    }
+
+    /// Keys pressed together, in the order they go down.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Chord {
+        pub keys: Vec<&'static Keycode>,
+    }
+
+    impl Display for Chord {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            let names: Vec<&str> = self.keys.iter().map(|k| k.name).collect();
+            write!(f, "{}", names.join("+"))
+        }
+    }
+
+    /// Parses a chord like `CTRL+SHIFT+A`: key names or aliases joined by `+`, with case and
+    /// spaces around names ignored. Each key may appear once, and at most [`MAX_CHORD_KEYS`] of
+    /// them can be anything other than modifiers.
+    pub fn parse_chord(text: &str) -> Result<Chord, String> {
+        if text.trim().is_empty() {
+            return Err("Empty chord".to_string());
+        }
+
+        let mut keys: Vec<&'static Keycode> = Vec::new();
+        for name in text.split('+').map(str::trim) {
+            if name.is_empty() {
+                return Err(format!("Missing key name in \"{text}\""));
+            }
+            let Some(key) = lookup(name) else {
+                return Err(match complete(name).first() {
+                    Some(suggestion) => {
+                        format!("Unknown key \"{name}\", did you mean {suggestion}?")
+                    }
+                    None => format!("Unknown key \"{name}\""),
+                });
+            };
+            if keys.contains(&key) {
+                return Err(format!("{} appears twice in \"{text}\"", key.name));
+            }
+            keys.push(key);
+        }
+
+        if keys.iter().filter(|k| !k.is_modifier()).count() > MAX_CHORD_KEYS {
+            return Err(format!(
+                "More than {MAX_CHORD_KEYS} keys besides modifiers in \"{text}\""
+            ));
+        }
+        Ok(Chord { keys })
+    }
+
+    /// `text` with every key under its canonical name, or unchanged if it doesn't parse.
+    pub fn canonical(text: &str) -> String {
+        parse_chord(text).map_or(text.to_string(), |chord| chord.to_string())
+    }
+
+    /// Ways to finish the last key name in a partly typed chord, as whole chords. Keys already in
+    /// the chord aren't offered again.
+    pub fn complete(text: &str) -> Vec<String> {
+        let (head, partial) = match text.rfind('+') {
+            Some(at) => (&text[..=at], &text[at + 1..]),
+            None => ("", text),
+        };
+        let partial = partial.trim().to_ascii_uppercase();
+        let taken: Vec<&Keycode> = head.split('+').filter_map(lookup).collect();
+
+        KEYCODES
+            .iter()
+            .map(|k| (k.name, k.name))
+            .chain(ALIASES.iter().chain(SHORTHANDS.iter()).copied())
+            .filter(|(name, _)| name.starts_with(&partial))
+            .filter(|(_, target)| !taken.iter().any(|k| k.name == *target))
+            .map(|(name, _)| format!("{head}{name}"))
+            .collect()
+    }
+}
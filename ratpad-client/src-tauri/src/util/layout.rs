@@ -0,0 +1,168 @@
+pub mod keyboard_layout {
+    use std::{collections::HashMap, sync::OnceLock};
+
+    use serde::{Deserialize, Serialize};
+
+    /// The keyboard layout the host interprets the pad's keycodes with. The pad always sends
+    /// physical key positions, so typing text means knowing which key makes which character.
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+    pub enum KeyboardLayout {
+        #[default]
+        #[serde(rename = "us")]
+        Us,
+
+        #[serde(rename = "uk")]
+        Uk,
+
+        #[serde(rename = "de")]
+        De,
+
+        #[serde(rename = "fr")]
+        Fr,
+    }
+
+    /// What to do with characters the layout has no key for.
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+    pub enum TextFallback {
+        /// Enter the code point with Ctrl+Shift+U, as GTK and IBus accept.
+        #[default]
+        #[serde(rename = "unicode_input")]
+        UnicodeInput,
+
+        #[serde(rename = "skip")]
+        Skip,
+    }
+
+    /// Most chords sent to the pad in one `send_sequence` line. The firmware drops lines it
+    /// can't read in one go, so these are kept short.
+    pub const SEQUENCE_CHUNK: usize = 16;
+
+    /// A layout file (`layouts/*.json`): every character the layout can type, mapped to the
+    /// chords that type it, separated by spaces when a dead key is involved.
+    #[derive(Deserialize)]
+    struct LayoutTable {
+        keys: HashMap<char, String>,
+    }
+
+    fn parse(data: &str) -> LayoutTable {
+        serde_json::from_str(data).expect("Invalid built-in keyboard layout")
+    }
+
+    fn table(layout: KeyboardLayout) -> &'static LayoutTable {
+        static US: OnceLock<LayoutTable> = OnceLock::new();
+        static UK: OnceLock<LayoutTable> = OnceLock::new();
+        static DE: OnceLock<LayoutTable> = OnceLock::new();
+        static FR: OnceLock<LayoutTable> = OnceLock::new();
+
+        match layout {
+            KeyboardLayout::Us => US.get_or_init(|| parse(include_str!("../../layouts/us.json"))),
+            KeyboardLayout::Uk => UK.get_or_init(|| parse(include_str!("../../layouts/uk.json"))),
+            KeyboardLayout::De => DE.get_or_init(|| parse(include_str!("../../layouts/de.json"))),
+            KeyboardLayout::Fr => FR.get_or_init(|| parse(include_str!("../../layouts/fr.json"))),
+        }
+    }
+
+    fn chords_for(table: &LayoutTable, c: char) -> Option<Vec<String>> {
+        table.keys.get(&c).map(|chords| {
+            chords
+                .split_whitespace()
+                .map(|chord| chord.to_string())
+                .collect()
+        })
+    }
+
+    /// The chords that type `text` on `layout`, in order. Carriage returns are dropped so
+    /// Windows line endings don't press Enter twice.
+    pub fn translate(text: &str, layout: KeyboardLayout, fallback: TextFallback) -> Vec<String> {
+        let table = table(layout);
+        let mut chords = Vec::new();
+
+        for c in text.chars().filter(|c| *c != '\r') {
+            if let Some(typed) = chords_for(table, c) {
+                chords.extend(typed);
+                continue;
+            }
+
+            if fallback == TextFallback::UnicodeInput {
+                let code = format!("{:x}", c as u32)
+                    .chars()
+                    .map(|digit| chords_for(table, digit))
+                    .collect::<Option<Vec<_>>>()
+                    .map(|digits| digits.concat());
+                if let (Some(digits), Some(commit)) = (code, chords_for(table, ' ')) {
+                    chords.push("CONTROL+SHIFT+U".to_string());
+                    chords.extend(digits);
+                    chords.extend(commit);
+                }
+            }
+        }
+
+        chords
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::util::keycode_catalog::parse_chord;
+
+        const LAYOUTS: [KeyboardLayout; 4] = [
+            KeyboardLayout::Us,
+            KeyboardLayout::Uk,
+            KeyboardLayout::De,
+            KeyboardLayout::Fr,
+        ];
+
+        #[test]
+        fn types_text_for_each_layout() {
+            let typed = |text, layout| translate(text, layout, TextFallback::Skip);
+            assert_eq!(typed("zy", KeyboardLayout::Us), ["Z", "Y"]);
+            assert_eq!(typed("zy", KeyboardLayout::De), ["Y", "Z"]);
+            assert_eq!(typed("@", KeyboardLayout::Uk), ["SHIFT+QUOTE"]);
+            assert_eq!(typed("a\r\n", KeyboardLayout::Us), ["A", "ENTER"]);
+        }
+
+        #[test]
+        fn presses_dead_keys_before_the_letter() {
+            assert_eq!(
+                translate("â", KeyboardLayout::Fr, TextFallback::Skip),
+                ["LEFT_BRACKET", "Q"]
+            );
+            assert_eq!(
+                translate("é", KeyboardLayout::De, TextFallback::Skip),
+                ["EQUALS", "E"]
+            );
+        }
+
+        #[test]
+        fn falls_back_to_unicode_input_or_skips() {
+            assert_eq!(
+                translate("é", KeyboardLayout::Us, TextFallback::UnicodeInput),
+                ["CONTROL+SHIFT+U", "E", "NINE", "SPACE"]
+            );
+            assert_eq!(
+                translate("aéb", KeyboardLayout::Us, TextFallback::Skip),
+                ["A", "B"]
+            );
+        }
+
+        #[test]
+        fn every_layout_types_ascii_with_known_keys() {
+            for layout in LAYOUTS {
+                for (c, chords) in table(layout).keys.iter() {
+                    for chord in chords.split_whitespace() {
+                        assert!(
+                            parse_chord(chord).is_ok(),
+                            "{layout:?} types {c:?} with unknown chord {chord}"
+                        );
+                    }
+                }
+                for c in (' '..='~').chain(['\n', '\t']) {
+                    assert!(
+                        table(layout).keys.contains_key(&c),
+                        "{layout:?} can't type {c:?}"
+                    );
+                }
+            }
+        }
+    }
+}
@@ -10,5 +10,56 @@ pub use state::app_state;
 mod config;
 pub use config::configuration;
 
+mod store;
+pub use store::config_store;
+
+mod format;
+pub use format::config_format;
+
+mod journal;
+pub use journal::config_journal;
+
 mod commands;
-pub use commands::command_handler;
\ No newline at end of file
+pub use commands::command_handler;
+
+mod executor;
+pub use executor::action_executor;
+
+mod requests;
+pub use requests::request_router;
+
+mod handshake;
+pub use handshake::pad_handshake;
+
+mod runtime;
+pub use runtime::pad_runtime;
+
+mod sync;
+pub use sync::config_sync;
+
+mod control;
+pub use control::control_socket;
+
+mod transport;
+pub use transport::pad_transport;
+
+mod hotplug;
+pub use hotplug::device_watcher;
+
+mod focus;
+pub use focus::focus_watcher;
+
+mod reload;
+pub use reload::config_watcher;
+
+mod layout;
+pub use layout::keyboard_layout;
+
+mod keycodes;
+pub use keycodes::keycode_catalog;
+
+mod inject;
+pub use inject::input_injection;
+
+mod simulator;
+pub use simulator::pad_simulator;
@@ -1,6 +1,7 @@
 pub mod ratpad_communication {
     use serde::{Deserialize, Serialize};
     use serde_json::{Error, Value};
+    use ts_rs::TS;
 
     #[derive(Clone, Debug, PartialEq, Eq)]
     pub enum EventType {
@@ -9,6 +10,7 @@ pub mod ratpad_communication {
         Disconnect,
         Log,
         Config,
+        Capabilities,
     }
 
     #[derive(Clone, Debug, PartialEq, Eq)]
@@ -19,7 +21,11 @@ pub mod ratpad_communication {
         ClearModes,
         ReadConfig,
         SetMode,
-        SetHome
+        SetHome,
+        Hello,
+        SendKeys,
+        SendSequence,
+        SendMouse,
     }
 
     #[derive(Clone, Debug, PartialEq, Eq)]
@@ -57,6 +63,7 @@ pub mod ratpad_communication {
                     EventType::Disconnect => "disconnect",
                     EventType::Event => "event",
                     EventType::Log => "log",
+                    EventType::Capabilities => "capabilities",
                 },
                 MessageType::Command(subtype) => match subtype {
                     CommandType::ClearModes => "clear_modes",
@@ -65,7 +72,11 @@ pub mod ratpad_communication {
                     CommandType::SetColor => "set_color",
                     CommandType::WriteMode => "write_mode",
                     CommandType::SetMode => "set_mode",
-                    CommandType::SetHome => "set_home"
+                    CommandType::SetHome => "set_home",
+                    CommandType::Hello => "hello",
+                    CommandType::SendKeys => "send_keys",
+                    CommandType::SendSequence => "send_sequence",
+                    CommandType::SendMouse => "send_mouse",
                 },
                 MessageType::Unknown => "unknown",
             }
@@ -83,6 +94,13 @@ pub mod ratpad_communication {
                 "read_config" => MessageType::Command(CommandType::ReadConfig),
                 "set_color" => MessageType::Command(CommandType::SetColor),
                 "write_mode" => MessageType::Command(CommandType::WriteMode),
+                "set_mode" => MessageType::Command(CommandType::SetMode),
+                "set_home" => MessageType::Command(CommandType::SetHome),
+                "hello" => MessageType::Command(CommandType::Hello),
+                "send_keys" => MessageType::Command(CommandType::SendKeys),
+                "send_sequence" => MessageType::Command(CommandType::SendSequence),
+                "send_mouse" => MessageType::Command(CommandType::SendMouse),
+                "capabilities" => MessageType::Event(EventType::Capabilities),
                 _ => MessageType::Unknown,
             }
         }
@@ -92,39 +110,70 @@ pub mod ratpad_communication {
     pub struct Message {
         pub message_type: MessageType,
         pub data: Option<Value>,
+
+        /// Correlation id, echoed back by the pad on the reply to a request.
+        #[serde(default)]
+        pub id: Option<u64>,
+    }
+
+    fn create_header(message_type: &MessageType, id: Option<u64>) -> String {
+        let name = message_type.as_str();
+        if let Some(request) = id {
+            format!("{name}#{request}")
+        } else {
+            name.to_string()
+        }
+    }
+
+    fn parse_header(header: &str) -> (MessageType, Option<u64>) {
+        if let Some((name, request)) = header.split_once("#") {
+            (
+                MessageType::from_str(&name.to_ascii_lowercase()),
+                request.parse::<u64>().ok(),
+            )
+        } else {
+            (MessageType::from_str(&header.to_ascii_lowercase()), None)
+        }
     }
 
     pub fn create_message(message: Message) -> Result<String, Error> {
-        let message_type = message.message_type.as_str();
+        let header = create_header(&message.message_type, message.id);
         let data = message.data;
         if let Some(confirmed_data) = data {
             match serde_json::to_string(&confirmed_data) {
-                Ok(ser) => Ok(format!("{message_type}:{ser};\n")),
+                Ok(ser) => Ok(format!("{header}:{ser};\n")),
                 Err(err) => Err(err),
             }
         } else {
-            Ok(format!("{message_type}:;\n"))
+            Ok(format!("{header}:;\n"))
         }
     }
 
     pub fn create_empty_message(message_type: MessageType) -> Result<String, Error> {
-        create_message(Message {message_type, data: None})
+        create_message(Message {
+            message_type,
+            data: None,
+            id: None,
+        })
     }
 
     pub fn parse_message(msg: String) -> Option<Message> {
         if let Some((header, data)) = msg.trim().trim_end_matches(';').split_once(":") {
+            let (message_type, id) = parse_header(header);
             if data.len() > 0 {
                 match serde_json::from_str(data) {
                     Ok(parsed) => Some(Message {
-                        message_type: MessageType::from_str(&*header.to_ascii_lowercase()),
+                        message_type,
                         data: parsed,
+                        id,
                     }),
                     Err(_) => None,
                 }
             } else {
                 Some(Message {
-                    message_type: MessageType::from_str(&*header.to_ascii_lowercase()),
+                    message_type,
                     data: None,
+                    id,
                 })
             }
         } else {
@@ -132,7 +181,56 @@ pub mod ratpad_communication {
         }
     }
 
-    #[derive(Serialize, Deserialize, Clone, Debug)]
+    /// Version of the line protocol spoken by this client, sent to the pad in the `hello` command.
+    pub const PROTOCOL_VERSION: u32 = 1;
+
+    /// What the connected pad reported about itself during the handshake.
+    #[derive(Serialize, Deserialize, Clone, Debug, TS)]
+    #[ts(export, export_to = "../../src/api/types/bindings/")]
+    pub struct PadCapabilities {
+        pub firmware: String,
+        pub protocol: u32,
+        pub commands: Vec<String>,
+        pub keys: u32,
+
+        /// Encoder reading when the pad answered, so the first rotation has a baseline.
+        #[serde(default)]
+        #[ts(optional)]
+        pub encoder: Option<i32>,
+    }
+
+    impl PadCapabilities {
+        /// Capabilities assumed for firmware that predates the handshake. Requests to it are sent
+        /// without ids, which it wouldn't understand.
+        pub fn legacy() -> PadCapabilities {
+            PadCapabilities {
+                firmware: "unknown".to_string(),
+                protocol: 0,
+                commands: [
+                    CommandType::SetColor,
+                    CommandType::WriteMode,
+                    CommandType::DeleteMode,
+                    CommandType::ClearModes,
+                    CommandType::ReadConfig,
+                    CommandType::SetMode,
+                    CommandType::SetHome,
+                ]
+                .iter()
+                .map(|c| MessageType::Command(c.clone()).as_str().to_string())
+                .collect(),
+                keys: 9,
+                encoder: None,
+            }
+        }
+
+        pub fn supports(&self, command: &CommandType) -> bool {
+            let name = MessageType::Command(command.clone()).as_str();
+            self.commands.iter().any(|c| c == name)
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, TS)]
+    #[ts(export, export_to = "../../src/api/types/bindings/")]
     pub struct ColorsConfig {
         pub next: (u32, u32, u32),
         pub previous: (u32, u32, u32),
@@ -154,14 +252,16 @@ pub mod ratpad_communication {
         Brightness,
     }
 
-    #[derive(Serialize, Deserialize, Clone, Debug)]
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, TS)]
+    #[ts(export, export_to = "../../src/api/types/bindings/")]
     pub struct ModeKey {
         pub label: String,
         pub keys: Option<String>,
         pub color: Option<(u32, u32, u32)>,
     }
 
-    #[derive(Serialize, Deserialize, Clone, Debug)]
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, TS)]
+    #[ts(export, export_to = "../../src/api/types/bindings/")]
     pub struct ModeConfig {
         pub key: String,
         pub title: String,
@@ -170,9 +270,229 @@ pub mod ratpad_communication {
         pub color: Option<(u32, u32, u32)>,
     }
 
-    #[derive(Serialize, Deserialize, Clone, Debug)]
+    #[derive(Serialize, Deserialize, Clone, Debug, TS)]
+    #[ts(export, export_to = "../../src/api/types/bindings/")]
     pub struct PadConfig {
         pub colors: ColorsConfig,
         pub modes: Vec<ModeConfig>,
     }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, TS)]
+    #[ts(export, export_to = "../../src/api/types/bindings/")]
+    pub struct Key {
+        pub code: u32,
+        pub name: String,
+
+        /// 0-based action slot, negative for the navigation keys.
+        pub action: i32,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, TS)]
+    #[ts(export, export_to = "../../src/api/types/bindings/")]
+    pub enum LogLevel {
+        #[serde(rename = "debug")]
+        Debug,
+
+        #[serde(rename = "info")]
+        Info,
+
+        #[serde(rename = "warning")]
+        Warning,
+
+        #[serde(rename = "error")]
+        Error,
+
+        #[serde(rename = "critical")]
+        Critical,
+    }
+
+    /// A message from the pad, decoded into what it means.
+    #[derive(Serialize, Deserialize, Clone, Debug, TS)]
+    #[serde(tag = "type")]
+    #[ts(export, export_to = "../../src/api/types/bindings/")]
+    pub enum PadEvent {
+        #[serde(rename = "key.pressed")]
+        KeyPressed { mode: Option<String>, key: Key },
+
+        #[serde(rename = "encoder.switch")]
+        EncoderSwitch { mode: Option<String>, pressed: bool },
+
+        /// `delta` is relative to the previous reading from this pad, and 0 for the first one.
+        #[serde(rename = "encoder.rotated")]
+        EncoderRotated {
+            mode: Option<String>,
+            value: i32,
+            delta: i32,
+        },
+
+        #[serde(rename = "mode.changed")]
+        ModeChanged { mode: Option<String> },
+
+        #[serde(rename = "log")]
+        Log { content: String, level: LogLevel },
+
+        #[serde(rename = "config")]
+        Config { config: PadConfig },
+
+        #[serde(rename = "connect")]
+        Connect,
+
+        #[serde(rename = "disconnect")]
+        Disconnect,
+
+        #[serde(rename = "capabilities")]
+        Capabilities { capabilities: PadCapabilities },
+
+        /// Anything that didn't decode, passed through untouched.
+        #[serde(rename = "unknown")]
+        Unknown {
+            #[ts(type = "string")]
+            message_type: MessageType,
+            #[ts(type = "unknown")]
+            data: Option<Value>,
+        },
+    }
+
+    /// Payload of an `event` message, as the pad writes it.
+    #[derive(Deserialize)]
+    #[serde(tag = "type")]
+    enum RawEvent {
+        #[serde(rename = "key")]
+        Key { mode: Option<String>, key: Key },
+
+        #[serde(rename = "encoder.switch")]
+        EncoderSwitch { mode: Option<String>, pressed: bool },
+
+        #[serde(rename = "encoder.value")]
+        EncoderValue { mode: Option<String>, value: i32 },
+
+        /// Newer firmware sends the encoder reading along, as it keeps counting outside modes.
+        #[serde(rename = "mode")]
+        Mode {
+            mode: Option<String>,
+            #[serde(default)]
+            encoder: Option<i32>,
+        },
+    }
+
+    #[derive(Deserialize)]
+    struct RawLog {
+        content: String,
+        level: LogLevel,
+    }
+
+    /// Turns messages from one pad into [`PadEvent`]s. Keeps the last encoder reading so rotations
+    /// can carry a delta, so each connection needs its own.
+    #[derive(Default)]
+    pub struct PadEventDecoder {
+        encoder: Option<i32>,
+    }
+
+    impl PadEventDecoder {
+        pub fn new() -> PadEventDecoder {
+            PadEventDecoder { encoder: None }
+        }
+
+        fn decode_event(&mut self, raw: RawEvent) -> PadEvent {
+            match raw {
+                RawEvent::Key { mode, key } => PadEvent::KeyPressed { mode, key },
+                RawEvent::EncoderSwitch { mode, pressed } => {
+                    PadEvent::EncoderSwitch { mode, pressed }
+                }
+                RawEvent::EncoderValue { mode, value } => {
+                    let delta = self.encoder.map_or(0, |last| value - last);
+                    self.encoder = Some(value);
+                    PadEvent::EncoderRotated { mode, value, delta }
+                }
+                RawEvent::Mode { mode, encoder } => {
+                    // Turns made outside a mode aren't reported, so they mustn't count as one.
+                    self.encoder = encoder;
+                    PadEvent::ModeChanged { mode }
+                }
+            }
+        }
+
+        pub fn decode(&mut self, message: &Message) -> PadEvent {
+            let data = message.data.clone();
+            let decoded = match &message.message_type {
+                MessageType::Event(EventType::Event) => data
+                    .clone()
+                    .and_then(|d| serde_json::from_value::<RawEvent>(d).ok())
+                    .map(|raw| self.decode_event(raw)),
+                MessageType::Event(EventType::Log) => data
+                    .clone()
+                    .and_then(|d| serde_json::from_value::<RawLog>(d).ok())
+                    .map(|log| PadEvent::Log {
+                        content: log.content,
+                        level: log.level,
+                    }),
+                MessageType::Event(EventType::Config) => data
+                    .clone()
+                    .and_then(|d| serde_json::from_value::<PadConfig>(d).ok())
+                    .map(|config| PadEvent::Config { config }),
+                MessageType::Event(EventType::Capabilities) => data
+                    .clone()
+                    .and_then(|d| serde_json::from_value::<PadCapabilities>(d).ok())
+                    .map(|capabilities| {
+                        if capabilities.encoder.is_some() {
+                            self.encoder = capabilities.encoder;
+                        }
+                        PadEvent::Capabilities { capabilities }
+                    }),
+                MessageType::Event(EventType::Connect) => {
+                    self.encoder = None;
+                    Some(PadEvent::Connect)
+                }
+                MessageType::Event(EventType::Disconnect) => Some(PadEvent::Disconnect),
+                _ => None,
+            };
+
+            decoded.unwrap_or(PadEvent::Unknown {
+                message_type: message.message_type.clone(),
+                data,
+            })
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use serde_json::json;
+
+        use super::*;
+
+        fn event(data: Value) -> Message {
+            Message {
+                message_type: MessageType::Event(EventType::Event),
+                data: Some(data),
+                id: None,
+            }
+        }
+
+        fn delta(decoder: &mut PadEventDecoder, value: i32) -> i32 {
+            match decoder.decode(&event(
+                json!({"type": "encoder.value", "mode": "m", "value": value}),
+            )) {
+                PadEvent::EncoderRotated { delta, .. } => delta,
+                other => panic!("Expected a rotation, got {other:?}"),
+            }
+        }
+
+        #[test]
+        fn turns_outside_a_mode_dont_count() {
+            let mut decoder = PadEventDecoder::new();
+            decoder.decode(&event(json!({"type": "mode", "mode": "m", "encoder": 0})));
+            assert_eq!(delta(&mut decoder, 1), 1);
+
+            // Turned four detents on the home screen, then back into a mode and one more.
+            decoder.decode(&event(json!({"type": "mode", "mode": null, "encoder": 1})));
+            decoder.decode(&event(json!({"type": "mode", "mode": "m", "encoder": 5})));
+            assert_eq!(delta(&mut decoder, 6), 1);
+
+            // Older firmware doesn't send the reading, so the first click only sets the baseline.
+            decoder.decode(&event(json!({"type": "mode", "mode": null})));
+            decoder.decode(&event(json!({"type": "mode", "mode": "m"})));
+            assert_eq!(delta(&mut decoder, 10), 0);
+            assert_eq!(delta(&mut decoder, 9), -1);
+        }
+    }
 }
@@ -0,0 +1,138 @@
+pub mod config_watcher {
+    use std::{
+        sync::mpsc::{channel, RecvTimeoutError},
+        thread,
+        time::Duration,
+    };
+
+    use notify::{recommended_watcher, Event, RecursiveMode, Watcher};
+
+    use crate::util::{
+        config_format::{config_path, ConfigFormat},
+        config_store::{read_config, report, ConfigEvent, BACKUP_DIR},
+        config_sync::{compute_diff, push_changes},
+        configuration::{AppConfig, PadCompat},
+        pad_runtime::PadRuntime,
+    };
+
+    /// Editors often save in several writes, so the file is only read once it has been left
+    /// alone this long.
+    const SETTLE: Duration = Duration::from_millis(300);
+
+    /// Checks what the client would refuse to write through its own commands, and that every
+    /// include loaded.
+    fn validate(config: &AppConfig) -> Result<(), String> {
+        if let Some(reason) = config.include_errors.first() {
+            return Err(reason.clone());
+        }
+        for mode in config.modes.iter() {
+            mode.check_chords()
+                .and_then(|_| mode.check_focus())
+                .map_err(|e| format!("Mode {}: {e}", mode.key()))?;
+            config
+                .check_parent(mode)
+                .map_err(|e| format!("Mode {}: {e}", mode.key()))?;
+        }
+        Ok(())
+    }
+
+    /// Included modes always load after the rest, so the order a save left them in can't be
+    /// told apart from a reorder and isn't counted as a change.
+    fn unordered(config: &AppConfig) -> Option<String> {
+        let mut config = config.clone();
+        config.modes.sort_by_key(|m| m.key());
+        config.to_json().ok()
+    }
+
+    /// Loads the edited file and pushes what changed to each connected pad. Writes the client
+    /// made itself read back the same as the running config, and are left alone. The file is read
+    /// with the config locked, as the client saves while holding the lock too.
+    fn reload(handle: &PadRuntime) {
+        let dir = handle.config_dir();
+        let path = config_path(&dir);
+        let Some(mut config) = handle.state().lock_config() else {
+            return;
+        };
+        if !path.exists() {
+            return;
+        }
+
+        let update = match read_config(&path, &dir)
+            .and_then(|(update, _)| validate(&update).map(|_| update))
+        {
+            Ok(update) => update,
+            Err(reason) => {
+                drop(config);
+                report(handle, ConfigEvent::ReloadFailed { reason });
+                return;
+            }
+        };
+        if unordered(&config) == unordered(&update) {
+            // Removing a duplicate include fixes the config without changing it.
+            config.include_errors.clear();
+            return;
+        }
+        let previous = config.clone();
+        config.set(update.clone());
+        // The history was made against the config the file replaced.
+        if let Some(mut journal) = handle.state().lock_journal() {
+            journal.clear();
+        }
+        drop(config);
+
+        push_changes(handle.clone(), &previous, &update);
+
+        let diff = compute_diff(&update, &previous.to_pad());
+        handle.emit("ratpad://config", ConfigEvent::Reloaded { diff });
+    }
+
+    /// Watches the config directory rather than the file, as saving by renaming over it (which
+    /// the client and most editors do) would leave a watch on the file pointing at the old one.
+    /// Included files are only watched when they are inside the config directory.
+    fn watch(handle: PadRuntime) -> notify::Result<()> {
+        let dir = handle.config_dir();
+        let backups = dir.join(BACKUP_DIR);
+        let (sender, receiver) = channel::<notify::Result<Event>>();
+        let mut watcher = recommended_watcher(sender)?;
+        watcher.watch(&dir, RecursiveMode::Recursive)?;
+
+        // Reading the file raises access events too, which mustn't set off another reload.
+        let touches_config = |event: notify::Result<Event>| {
+            event.is_ok_and(|e| {
+                (e.kind.is_create() || e.kind.is_modify() || e.kind.is_remove())
+                    && e.paths
+                        .iter()
+                        .any(|p| !p.starts_with(&backups) && ConfigFormat::from_path(p).is_some())
+            })
+        };
+
+        loop {
+            let Ok(event) = receiver.recv() else {
+                return Ok(());
+            };
+            if !touches_config(event) {
+                continue;
+            }
+
+            loop {
+                match receiver.recv_timeout(SETTLE) {
+                    Ok(_) => continue,
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => return Ok(()),
+                }
+            }
+            reload(&handle);
+        }
+    }
+
+    /// Reloads the config whenever it, or a file it includes, is changed outside the client, so
+    /// hand edits and synced dotfiles reach the pads without a restart.
+    pub fn start_config_watcher(runtime: &PadRuntime) {
+        let handle = runtime.clone();
+        thread::spawn(move || {
+            if let Err(error) = watch(handle.clone()) {
+                handle.report_failure("config_watcher", error);
+            }
+        });
+    }
+}
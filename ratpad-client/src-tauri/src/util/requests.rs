@@ -0,0 +1,195 @@
+pub mod request_router {
+    use std::{
+        collections::HashMap,
+        sync::mpsc::{self, Receiver, Sender},
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        ratpad_communication::{Message, MessageType},
+        serial_client::SerialEvent,
+        util::pad_runtime::PadRuntime,
+    };
+
+    pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+    struct PendingRequest {
+        device: String,
+        expects: MessageType,
+
+        /// Whether the request went out with its id. Firmware from before the handshake doesn't
+        /// understand ids, so it is sent requests without one and answers without one.
+        tagged: bool,
+        reply: Sender<Message>,
+        deadline: Instant,
+    }
+
+    /// Tracks requests sent to the pad that are still waiting on a reply, keyed by correlation id.
+    pub struct RequestTable {
+        next_id: u64,
+        pending: HashMap<u64, PendingRequest>,
+    }
+
+    impl Default for RequestTable {
+        fn default() -> Self {
+            RequestTable::new()
+        }
+    }
+
+    impl RequestTable {
+        pub fn new() -> RequestTable {
+            RequestTable {
+                next_id: 1,
+                pending: HashMap::new(),
+            }
+        }
+
+        pub fn register(
+            &mut self,
+            device: String,
+            expects: MessageType,
+            tagged: bool,
+            timeout: Duration,
+        ) -> (u64, Receiver<Message>) {
+            self.expire();
+            let id = self.next_id;
+            self.next_id = self.next_id.wrapping_add(1).max(1);
+
+            let (tx, rx) = mpsc::channel::<Message>();
+            self.pending.insert(
+                id,
+                PendingRequest {
+                    device,
+                    expects,
+                    tagged,
+                    reply: tx,
+                    deadline: Instant::now() + timeout,
+                },
+            );
+            (id, rx)
+        }
+
+        pub fn cancel(&mut self, id: u64) {
+            self.pending.remove(&id);
+        }
+
+        /// Drops every request whose deadline has passed, disconnecting its waiter.
+        pub fn expire(&mut self) {
+            let now = Instant::now();
+            self.pending.retain(|_, request| request.deadline > now);
+        }
+
+        /// Delivers a reply from `device` to its waiter. Replies without an id go to the oldest
+        /// untagged request from that device expecting their type. Returns false if nothing was
+        /// waiting on it.
+        pub fn resolve(&mut self, device: &str, message: Message) -> bool {
+            self.expire();
+            let id = match message.id {
+                Some(id) => id,
+                None => {
+                    let oldest = self
+                        .pending
+                        .iter()
+                        .filter(|(_, request)| {
+                            !request.tagged
+                                && request.device == device
+                                && request.expects == message.message_type
+                        })
+                        .map(|(id, _)| *id)
+                        .min();
+                    let Some(id) = oldest else {
+                        return false;
+                    };
+                    id
+                }
+            };
+
+            match self.pending.get(&id) {
+                Some(request) if request.expects == message.message_type => {
+                    let request = self.pending.remove(&id).unwrap();
+                    request.reply.send(message).is_ok()
+                }
+                _ => false,
+            }
+        }
+    }
+
+    pub fn start_request_router(runtime: &PadRuntime) {
+        let handle = runtime.clone();
+        runtime.listen("ratpad://serial", move |payload| {
+            if let Some(payload) = payload {
+                if let Ok(SerialEvent::Event {
+                    device, message, ..
+                }) = serde_json::from_str::<SerialEvent>(payload)
+                {
+                    if let Some(mut requests) = handle.state().lock_requests() {
+                        requests.resolve(&device, message);
+                    }
+                }
+            }
+        });
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::ratpad_communication::{CommandType, EventType};
+
+        fn reply(message_type: MessageType, id: Option<u64>) -> Message {
+            Message {
+                message_type,
+                data: None,
+                id,
+            }
+        }
+
+        #[test]
+        fn resolves_tagged_replies_by_id() {
+            let config = MessageType::Event(EventType::Config);
+            let mut table = RequestTable::new();
+            let (first, first_rx) =
+                table.register("a".into(), config.clone(), true, DEFAULT_REQUEST_TIMEOUT);
+            let (second, second_rx) =
+                table.register("a".into(), config.clone(), true, DEFAULT_REQUEST_TIMEOUT);
+
+            assert!(!table.resolve("a", reply(config.clone(), None)));
+            assert!(!table.resolve(
+                "a",
+                reply(MessageType::Command(CommandType::Hello), Some(second))
+            ));
+            assert!(table.resolve("a", reply(config.clone(), Some(second))));
+            assert_eq!(second_rx.try_recv().unwrap().id, Some(second));
+            assert!(first_rx.try_recv().is_err());
+            assert!(table.resolve("a", reply(config, Some(first))));
+        }
+
+        #[test]
+        fn resolves_untagged_replies_oldest_first_per_device() {
+            let config = MessageType::Event(EventType::Config);
+            let mut table = RequestTable::new();
+            let (_, first_rx) =
+                table.register("a".into(), config.clone(), false, DEFAULT_REQUEST_TIMEOUT);
+            let (_, other_rx) =
+                table.register("b".into(), config.clone(), false, DEFAULT_REQUEST_TIMEOUT);
+            let (_, second_rx) =
+                table.register("a".into(), config.clone(), false, DEFAULT_REQUEST_TIMEOUT);
+
+            assert!(table.resolve("a", reply(config.clone(), None)));
+            assert!(first_rx.try_recv().is_ok());
+            assert!(second_rx.try_recv().is_err());
+            assert!(table.resolve("a", reply(config.clone(), None)));
+            assert!(second_rx.try_recv().is_ok());
+            assert!(!table.resolve("a", reply(config, None)));
+            assert!(other_rx.try_recv().is_err());
+        }
+
+        #[test]
+        fn expired_requests_are_dropped() {
+            let config = MessageType::Event(EventType::Config);
+            let mut table = RequestTable::new();
+            let (id, rx) = table.register("a".into(), config.clone(), true, Duration::ZERO);
+            assert!(!table.resolve("a", reply(config, Some(id))));
+            assert!(rx.recv().is_err());
+        }
+    }
+}
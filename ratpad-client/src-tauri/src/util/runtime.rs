@@ -0,0 +1,192 @@
+pub mod pad_runtime {
+    use std::{
+        collections::HashMap,
+        env,
+        fmt::Display,
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    use serde::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    use crate::{
+        serial_client::{start_serial_listener, SerialEvent},
+        util::{
+            action_executor::start_action_executor,
+            app_state::ApplicationState,
+            config_journal::ConfigJournal,
+            config_sync::start_sync_listener,
+            config_watcher::start_config_watcher,
+            configuration::AppConfig,
+            control_socket::start_control_socket,
+            device_watcher::{connect_saved_devices, start_device_watcher},
+            focus_watcher::start_focus_watcher,
+            pad_handshake::start_handshake_listener,
+            request_router::{start_request_router, RequestTable},
+        },
+    };
+
+    /// Sent on "ratpad://service" when a background service can't start or stops, as hosts
+    /// without a console would otherwise never hear of it.
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct ServiceEvent {
+        /// Which service, like `device_watcher`.
+        pub service: String,
+        pub reason: String,
+    }
+
+    type Handler = Arc<dyn Fn(Option<&str>) + Send + Sync>;
+    type Emitter = Box<dyn Fn(&str, Value) + Send + Sync>;
+
+    struct Inner {
+        state: ApplicationState,
+        config_dir: PathBuf,
+        next_listener: AtomicU64,
+        listeners: Mutex<HashMap<String, Vec<(u64, Handler)>>>,
+        emitter: Mutex<Option<Emitter>>,
+    }
+
+    /// Everything the pad core needs from its host: shared state, an internal event bus, the
+    /// config location, and somewhere to send events meant for a front end. Cheap to clone.
+    #[derive(Clone)]
+    pub struct PadRuntime {
+        inner: Arc<Inner>,
+    }
+
+    impl PadRuntime {
+        pub fn new(config_dir: PathBuf) -> PadRuntime {
+            PadRuntime {
+                inner: Arc::new(Inner {
+                    state: ApplicationState {
+                        devices: Mutex::new(HashMap::new()),
+                        config: Mutex::new(AppConfig::default()),
+                        requests: Mutex::new(RequestTable::new()),
+                        config_event: Mutex::new(None),
+                        journal: Mutex::new(ConfigJournal::default()),
+                    },
+                    config_dir,
+                    next_listener: AtomicU64::new(1),
+                    listeners: Mutex::new(HashMap::new()),
+                    emitter: Mutex::new(None),
+                }),
+            }
+        }
+
+        pub fn state(&self) -> &ApplicationState {
+            &self.inner.state
+        }
+
+        pub fn config_dir(&self) -> PathBuf {
+            self.inner.config_dir.clone()
+        }
+
+        /// Registers a handler on the internal bus. Handlers run synchronously on the triggering thread.
+        pub fn listen<F: Fn(Option<&str>) + Send + Sync + 'static>(
+            &self,
+            event: &str,
+            handler: F,
+        ) -> u64 {
+            let id = self.inner.next_listener.fetch_add(1, Ordering::Relaxed);
+            if let Ok(mut listeners) = self.inner.listeners.lock() {
+                listeners
+                    .entry(event.to_string())
+                    .or_default()
+                    .push((id, Arc::new(handler)));
+            }
+            id
+        }
+
+        pub fn unlisten(&self, id: u64) {
+            if let Ok(mut listeners) = self.inner.listeners.lock() {
+                for handlers in listeners.values_mut() {
+                    handlers.retain(|(handler_id, _)| *handler_id != id);
+                }
+            }
+        }
+
+        pub fn trigger(&self, event: &str, payload: Option<String>) {
+            // Copied out so handlers can trigger or listen themselves.
+            let handlers: Vec<Handler> = match self.inner.listeners.lock() {
+                Ok(listeners) => listeners
+                    .get(event)
+                    .map(|h| h.iter().map(|(_, handler)| handler.clone()).collect())
+                    .unwrap_or_default(),
+                Err(_) => return,
+            };
+
+            for handler in handlers {
+                handler(payload.as_deref());
+            }
+        }
+
+        /// Sets where front end events go. Without one they are dropped.
+        pub fn set_emitter<F: Fn(&str, Value) + Send + Sync + 'static>(&self, emitter: F) {
+            if let Ok(mut current) = self.inner.emitter.lock() {
+                *current = Some(Box::new(emitter));
+            }
+        }
+
+        pub fn emit<S: Serialize>(&self, event: &str, payload: S) {
+            if let Ok(emitter) = self.inner.emitter.lock() {
+                if let (Some(emit), Ok(value)) = (emitter.as_ref(), serde_json::to_value(payload)) {
+                    emit(event, value);
+                }
+            }
+        }
+
+        pub fn report_failure(&self, service: &str, reason: impl Display) {
+            self.emit(
+                "ratpad://service",
+                ServiceEvent {
+                    service: service.to_string(),
+                    reason: reason.to_string(),
+                },
+            );
+        }
+    }
+
+    /// Loads the config, starts every service and connects the saved devices. Must be called from
+    /// inside a tokio runtime.
+    pub fn start_runtime(runtime: &PadRuntime) {
+        if let Some(mut config) = runtime.state().lock_config() {
+            config
+                .set(AppConfig::load_config(runtime.clone()))
+                .save(runtime.clone());
+        }
+
+        start_serial_listener(runtime);
+        start_request_router(runtime);
+        start_handshake_listener(runtime);
+        start_sync_listener(runtime);
+        start_action_executor(runtime);
+
+        let handle = runtime.clone();
+        runtime.listen("ratpad://serial", move |payload| {
+            if let Some(payload) = payload {
+                if let Ok(parsed) = serde_json::from_str::<SerialEvent>(payload) {
+                    handle.emit("ratpad://serial", parsed);
+                }
+            }
+        });
+
+        connect_saved_devices(runtime);
+        start_device_watcher(runtime);
+        start_control_socket(runtime);
+        start_focus_watcher(runtime);
+        start_config_watcher(runtime);
+    }
+
+    /// Where tauri puts the app config dir for the `com.ratpad` identifier, for hosts without tauri.
+    pub fn default_config_dir() -> PathBuf {
+        let base = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .expect("Unable to resolve config path");
+        base.join("com.ratpad")
+    }
+}
@@ -1,20 +1,24 @@
 pub mod serial_client {
-    use std::{
-        error::Error,
-        fmt,
-        io::{BufRead, BufReader, BufWriter, ErrorKind, Write},
-        sync::mpsc,
-        thread::{self, JoinHandle},
-        time::Duration,
-    };
+    use std::{collections::HashMap, error::Error, fmt, future::pending, io, time::Duration};
 
     use serde::{Deserialize, Serialize};
-    use tauri::{App, AppHandle, Manager};
-    use tokio_serial::{available_ports, new, SerialPort, SerialPortType, UsbPortInfo};
+    use tokio::{
+        io::{split, AsyncBufReadExt, AsyncWriteExt, BufReader, Lines, ReadHalf, WriteHalf},
+        select, spawn,
+        sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+        task::JoinHandle,
+        time::{sleep, timeout},
+    };
+    use tokio_serial::{available_ports, SerialPortType, UsbPortInfo};
 
     use crate::{
-        ratpad_communication::{create_message, parse_message, Message},
-        util::app_state::{ApplicationState, ConnectionState},
+        ratpad_communication::{create_message, parse_message, Message, PadEvent, PadEventDecoder},
+        util::{
+            app_state::ConnectionState,
+            device_watcher::identify_port,
+            pad_runtime::PadRuntime,
+            pad_transport::{parse_address, serial_port, BoxedStream, Transport},
+        },
     };
 
     #[derive(Serialize, Debug, Deserialize, Clone)]
@@ -58,6 +62,25 @@ pub mod serial_client {
         Unknown,
     }
 
+    /// USB identity of a pad, used to find it again when its port name changes.
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    pub struct DeviceIdentity {
+        pub vid: u16,
+        pub pid: u16,
+        pub serial_number: Option<String>,
+        pub interface: Option<String>,
+    }
+
+    impl DeviceIdentity {
+        /// Whether `other` is the same device. Fields missing from the saved identity match anything.
+        pub fn matches(&self, other: &DeviceIdentity) -> bool {
+            self.vid == other.vid
+                && self.pid == other.pid
+                && (self.serial_number.is_none() || self.serial_number == other.serial_number)
+                && (self.interface.is_none() || self.interface == other.interface)
+        }
+    }
+
     #[derive(Serialize, Deserialize, Clone)]
     pub struct PortInfo {
         port_name: String,
@@ -68,9 +91,44 @@ pub mod serial_client {
 
     #[derive(Serialize, Clone, Deserialize)]
     pub enum SerialEvent {
-        Event(Message),
-        Connect,
-        Disconnect,
+        Event {
+            device: String,
+            message: Message,
+            event: PadEvent,
+        },
+        Connect {
+            device: String,
+        },
+        Disconnect {
+            device: String,
+        },
+
+        /// The device couldn't be connected, like when its address doesn't parse. `device` is empty
+        /// when the listener was sent a command it couldn't read.
+        Error {
+            device: String,
+            reason: String,
+        },
+    }
+
+    impl SerialEvent {
+        pub fn device(&self) -> String {
+            match self {
+                SerialEvent::Event { device, .. } => device.clone(),
+                SerialEvent::Connect { device } => device.clone(),
+                SerialEvent::Disconnect { device } => device.clone(),
+                SerialEvent::Error { device, .. } => device.clone(),
+            }
+        }
+    }
+
+    impl PortInfo {
+        pub fn device_id(&self) -> Option<String> {
+            match &self.port_type {
+                SerialPortType::UsbPort(info) => info.serial_number.clone(),
+                _ => None,
+            }
+        }
     }
 
     pub fn get_ports() -> Result<Vec<PortInfo>, SerialError> {
@@ -90,171 +148,420 @@ pub mod serial_client {
         }
     }
 
+    /// The USB serial number of the pad on `port`, falling back to the port name.
+    pub fn device_id_for_port(port: &str) -> String {
+        available_ports()
+            .ok()
+            .and_then(|ports| ports.into_iter().find(|p| p.port_name == port))
+            .and_then(|p| match p.port_type {
+                SerialPortType::UsbPort(info) => info.serial_number,
+                _ => None,
+            })
+            .unwrap_or(port.to_string())
+    }
+
     #[derive(Serialize, Clone, Deserialize, Debug)]
     pub enum ListenerCommand {
-        Disconnect,
-        Connect { new_port: String, new_rate: u32 },
+        Disconnect {
+            device: String,
+        },
+        Connect {
+            device: String,
+            new_port: String,
+            new_rate: u32,
+        },
         Quit,
-        Send(Message),
+
+        /// Sends to one device, or to every connected device if `device` is `None`.
+        Send {
+            device: Option<String>,
+            message: Message,
+        },
     }
 
+    const INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+    const MAX_BACKOFF: Duration = Duration::from_secs(5);
+    const OPEN_TIMEOUT: Duration = Duration::from_secs(2);
+
+    type PortReader = Lines<BufReader<ReadHalf<BoxedStream>>>;
+    type PortWriter = WriteHalf<BoxedStream>;
+
     struct ListenerState {
-        port: String,
-        rate: u32,
-        reader: Option<BufReader<Box<dyn SerialPort>>>,
-        writer: Option<BufWriter<Box<dyn SerialPort>>>,
+        transport: Box<dyn Transport>,
+        reader: Option<PortReader>,
+        writer: Option<PortWriter>,
+        backoff: Duration,
     }
 
-    pub fn publish_serial_event(handle: AppHandle, event: SerialEvent) {
+    impl ListenerState {
+        fn new(transport: Box<dyn Transport>) -> ListenerState {
+            ListenerState {
+                transport,
+                reader: None,
+                writer: None,
+                backoff: INITIAL_BACKOFF,
+            }
+        }
+
+        async fn open(&mut self) -> bool {
+            match timeout(OPEN_TIMEOUT, self.transport.open()).await {
+                Ok(Ok(stream)) => {
+                    let (reader, writer) = split(stream);
+                    self.reader = Some(BufReader::new(reader).lines());
+                    self.writer = Some(writer);
+                    self.backoff = INITIAL_BACKOFF;
+                    true
+                }
+                _ => {
+                    self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+                    false
+                }
+            }
+        }
+
+        fn close(&mut self) {
+            self.reader = None;
+            self.writer = None;
+            self.backoff = INITIAL_BACKOFF;
+        }
+
+        fn is_open(&self) -> bool {
+            self.reader.is_some()
+        }
+    }
+
+    pub fn publish_serial_event(handle: PadRuntime, event: SerialEvent) {
         if let Ok(serialized) = serde_json::to_string::<SerialEvent>(&event) {
-            handle.trigger_global("ratpad://serial", Some(serialized));
+            handle.trigger("ratpad://serial", Some(serialized));
+        }
+    }
+
+    pub fn send_listener_command(handle: PadRuntime, command: ListenerCommand) {
+        if let Ok(serialized) = serde_json::to_string::<ListenerCommand>(&command) {
+            handle.trigger("ratpad://serial/cmd", Some(serialized));
         }
     }
 
-    pub fn send_serial_command(handle: AppHandle, command: Message) {
-        if let Ok(serialized) = serde_json::to_string::<ListenerCommand>(&ListenerCommand::Send(command)) {
-            handle.trigger_global("ratpad://serial/cmd", Some(serialized));
+    pub fn send_serial_command(handle: PadRuntime, device: Option<String>, command: Message) {
+        send_listener_command(
+            handle,
+            ListenerCommand::Send {
+                device,
+                message: command,
+            },
+        );
+    }
+
+    /// Resolves to the next line from the port, or never if the port is closed.
+    async fn read_line(state: &mut ListenerState) -> io::Result<Option<String>> {
+        match state.reader.as_mut() {
+            Some(reader) => reader.next_line().await,
+            None => pending().await,
         }
     }
 
-    pub fn start_serial_listener(app: &mut App) -> JoinHandle<()> {
-        let (tx, rx) = mpsc::channel::<ListenerCommand>();
+    /// Resolves once the reconnect backoff elapses, or never if the port is open.
+    async fn reconnect_delay(delay: Option<Duration>) {
+        match delay {
+            Some(backoff) => sleep(backoff).await,
+            None => pending().await,
+        }
+    }
 
-        app.listen_global("ratpad://serial/cmd", move |event| {
-            if let Some(command) = event.payload() {
-                if let Ok(parsed) = serde_json::from_str::<ListenerCommand>(command) {
-                    let _ = tx.send(parsed);
-                } else {
-                    println!("PARSE FAIL");
+    async fn write_message(state: &mut ListenerState, msg: Message) {
+        if let Some(writer) = state.writer.as_mut() {
+            if let Ok(ser) = create_message(msg) {
+                if writer.write_all(ser.as_bytes()).await.is_ok() {
+                    let _ = writer.flush().await;
                 }
             }
-        });
+        }
+    }
 
-        let handle = app.handle();
+    /// Owns one device's port until its command channel is dropped.
+    /// Opens the device's port, reporting it connected unless the device was dropped while the
+    /// open was under way.
+    async fn open_device(
+        handle: &PadRuntime,
+        device: &str,
+        state: &mut ListenerState,
+        rx: &UnboundedReceiver<Message>,
+    ) {
+        if state.open().await && !rx.is_closed() {
+            let app_state = handle.state();
+            app_state.set_connection_state(device.to_string(), ConnectionState::Connected);
+            publish_serial_event(
+                handle.clone(),
+                SerialEvent::Connect {
+                    device: device.to_string(),
+                },
+            );
+        }
+    }
 
-        let thread_result = thread::spawn(move || {
-            let mut state: Option<ListenerState> = None;
+    async fn run_device(
+        handle: PadRuntime,
+        device: String,
+        mut state: ListenerState,
+        mut rx: UnboundedReceiver<Message>,
+    ) {
+        // The first open happens here rather than in the listener, so a slow port doesn't hold up
+        // commands for the other devices.
+        open_device(&handle, &device, &mut state, &rx).await;
+        let mut decoder = PadEventDecoder::new();
+        loop {
+            let delay = if state.is_open() {
+                None
+            } else {
+                Some(state.backoff)
+            };
 
-            loop {
-                if let Ok(command) = rx.try_recv() {
-                    match command {
-                        ListenerCommand::Disconnect => {
-                            state = None;
-                            publish_serial_event(handle.clone(), SerialEvent::Disconnect);
-                            let app_state = handle.state::<ApplicationState>();
-                            app_state.set(ConnectionState::Disconnected, None, None);
-                            app_state.lock_config().unwrap().clear_connection().save(handle.clone());
+            select! {
+                command = rx.recv() => match command {
+                    Some(msg) => write_message(&mut state, msg).await,
+                    None => break,
+                },
+                line = read_line(&mut state) => match line {
+                    Ok(Some(read)) => {
+                        if let Some(msg) = parse_message(read) {
+                            publish_serial_event(
+                                handle.clone(),
+                                SerialEvent::Event {
+                                    device: device.clone(),
+                                    event: decoder.decode(&msg),
+                                    message: msg,
+                                },
+                            );
                         }
-                        ListenerCommand::Connect { new_port, new_rate } => {
-                            let builder =
-                                new(new_port.clone(), new_rate).timeout(Duration::from_millis(100));
-                            let opened = builder.open();
-                            if let Ok(serial) = opened {
-                                state = Some(ListenerState {
-                                    port: new_port.clone(),
-                                    rate: new_rate,
-                                    reader: Some(BufReader::with_capacity(
-                                        1,
-                                        serial.try_clone().unwrap(),
-                                    )),
-                                    writer: Some(BufWriter::with_capacity(
-                                        1,
-                                        serial.try_clone().unwrap(),
-                                    )),
-                                });
-                                publish_serial_event(handle.clone(), SerialEvent::Connect);
-                                let app_state = handle.state::<ApplicationState>();
-                                app_state.set(
-                                    ConnectionState::Connected,
-                                    Some(new_port.clone()),
-                                    Some(new_rate),
-                                );
-                                app_state.lock_config().unwrap().set_connection(new_port.clone(), new_rate).save(handle.clone());
-                            } else if let Err(_) = opened {
-                                state = Some(ListenerState {
-                                    port: new_port.clone(),
-                                    rate: new_rate,
-                                    reader: None,
-                                    writer: None,
-                                });
-                                let app_state = handle.state::<ApplicationState>();
-                                app_state.set(
-                                    ConnectionState::Waiting,
-                                    Some(new_port.clone()),
-                                    Some(new_rate),
-                                );
-                                app_state.lock_config().unwrap().set_connection(new_port.clone(), new_rate).save(handle.clone());
-                            }
+                    }
+                    Ok(None) | Err(_) => {
+                        state.close();
+                        let app_state = handle.state();
+                        app_state.set_connection_state(device.clone(), ConnectionState::Waiting);
+                        publish_serial_event(handle.clone(), SerialEvent::Disconnect { device: device.clone() });
+                    }
+                },
+                _ = reconnect_delay(delay) => open_device(&handle, &device, &mut state, &rx).await,
+            }
+        }
+    }
+
+    async fn run_listener(handle: PadRuntime, mut rx: UnboundedReceiver<ListenerCommand>) {
+        let mut devices: HashMap<String, UnboundedSender<Message>> = HashMap::new();
+
+        while let Some(command) = rx.recv().await {
+            match command {
+                ListenerCommand::Disconnect { device } => {
+                    devices.remove(&device);
+                    let app_state = handle.state();
+                    app_state.set(device.clone(), ConnectionState::Disconnected, None, None);
+                    if let Some(mut config) = app_state.lock_config() {
+                        config.clear_connection(device.clone()).save(handle.clone());
+                    }
+                    publish_serial_event(handle.clone(), SerialEvent::Disconnect { device });
+                }
+                ListenerCommand::Connect {
+                    device,
+                    new_port,
+                    new_rate,
+                } => {
+                    let transport = match parse_address(&new_port, new_rate) {
+                        Ok(transport) => transport,
+                        Err(reason) => {
+                            publish_serial_event(
+                                handle.clone(),
+                                SerialEvent::Error { device, reason },
+                            );
+                            continue;
                         }
-                        ListenerCommand::Quit => break,
-                        ListenerCommand::Send(msg) => {
-                            if let Some(ref mut st) = state {
-                                if let Some(ref mut writer) = st.writer {
-                                    if let Ok(ser) = create_message(msg) {
-                                        match writer.write_all(ser.as_bytes()) {
-                                            Ok(_) => {
-                                                let _ = writer.flush();
-                                            }
-                                            Err(_) => (),
-                                        }
-                                    }
-                                }
-                            }
+                    };
+                    let app_state = handle.state();
+                    app_state.set(
+                        device.clone(),
+                        ConnectionState::Waiting,
+                        Some(new_port.clone()),
+                        Some(new_rate),
+                    );
+                    if let Some(mut config) = app_state.lock_config() {
+                        config.set_connection(device.clone(), new_port.clone(), new_rate);
+                        if let Some(identity) =
+                            serial_port(&new_port).and_then(|port| identify_port(&port))
+                        {
+                            config.set_identity(device.clone(), identity);
                         }
+                        config.save(handle.clone());
+                    }
+
+                    let (tx, device_rx) = unbounded_channel::<Message>();
+                    devices.insert(device.clone(), tx);
+                    spawn(run_device(
+                        handle.clone(),
+                        device,
+                        ListenerState::new(transport),
+                        device_rx,
+                    ));
+                }
+                ListenerCommand::Send {
+                    device: Some(device),
+                    message,
+                } => {
+                    if let Some(tx) = devices.get(&device) {
+                        let _ = tx.send(message);
+                    }
+                }
+                ListenerCommand::Send {
+                    device: None,
+                    message,
+                } => {
+                    for tx in devices.values() {
+                        let _ = tx.send(message.clone());
                     }
                 }
+                ListenerCommand::Quit => break,
+            }
+        }
+    }
 
-                match state {
-                    Some(ref mut st) => {
-                        if st.reader.is_some() {
-                            let mut failure = false;
-                            for line in st.reader.as_mut().unwrap().lines() {
-                                match line {
-                                    Ok(ref read) => {
-                                        if let Some(msg) = parse_message(read.clone()) {
-                                            publish_serial_event(handle.clone(), SerialEvent::Event(msg));
-                                        }
-                                    }
-                                    Err(ref error) => match error.kind() {
-                                        ErrorKind::BrokenPipe => {
-                                            failure = true;
-                                            break;
-                                        }
-                                        ErrorKind::TimedOut => {
-                                            break;
-                                        }
-                                        _ => (),
-                                    },
-                                }
-                            }
-
-                            if failure {
-                                st.writer = None;
-                                st.reader = None;
-                                publish_serial_event(handle.clone(), SerialEvent::Disconnect);
-                                let app_state = handle.state::<ApplicationState>();
-                                app_state.set_connection_state(ConnectionState::Waiting);
-                            }
-                        } else {
-                            if let Ok(opened) = new(st.port.clone(), st.rate)
-                                .timeout(Duration::from_millis(100))
-                                .open()
-                            {
-                                st.reader =
-                                    Some(BufReader::with_capacity(1, opened.try_clone().unwrap()));
-                                st.writer =
-                                    Some(BufWriter::with_capacity(1, opened.try_clone().unwrap()));
-                                publish_serial_event(handle.clone(), SerialEvent::Connect);
-                                let app_state = handle.state::<ApplicationState>();
-                                app_state.set_connection_state(ConnectionState::Connected);
-                            }
-                        }
+    pub fn start_serial_listener(runtime: &PadRuntime) -> JoinHandle<()> {
+        let (tx, rx) = unbounded_channel::<ListenerCommand>();
+
+        let handle = runtime.clone();
+        runtime.listen("ratpad://serial/cmd", move |payload| {
+            if let Some(command) = payload {
+                match serde_json::from_str::<ListenerCommand>(command) {
+                    Ok(parsed) => {
+                        let _ = tx.send(parsed);
                     }
-                    None => (),
+                    Err(error) => publish_serial_event(
+                        handle.clone(),
+                        SerialEvent::Error {
+                            device: String::new(),
+                            reason: format!("Invalid listener command: {error}"),
+                        },
+                    ),
                 }
             }
         });
 
-        thread_result
+        spawn(run_listener(runtime.clone(), rx))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::{env::temp_dir, fs::remove_dir_all, process};
+
+        use serde_json::json;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        use super::*;
+        use crate::{
+            ratpad_communication::{CommandType, MessageType},
+            util::pad_transport::bind_memory,
+        };
+
+        const WAIT: Duration = Duration::from_secs(2);
+
+        /// A runtime with only the serial listener running, and the serial events it publishes.
+        fn listener(name: &str) -> (PadRuntime, UnboundedReceiver<SerialEvent>) {
+            let dir = temp_dir().join(format!("ratpad-{name}-{}", process::id()));
+            let runtime = PadRuntime::new(dir);
+            let (tx, rx) = unbounded_channel::<SerialEvent>();
+            runtime.listen("ratpad://serial", move |payload| {
+                if let Some(event) = payload.and_then(|p| serde_json::from_str(p).ok()) {
+                    let _ = tx.send(event);
+                }
+            });
+            start_serial_listener(&runtime);
+            (runtime, rx)
+        }
+
+        #[tokio::test]
+        async fn round_trips_messages_over_memory_transport() {
+            let (runtime, mut events) = listener("serial-mem");
+            let mut pads = bind_memory("serial-test");
+            send_listener_command(
+                runtime.clone(),
+                ListenerCommand::Connect {
+                    device: "pad".to_string(),
+                    new_port: "mem://serial-test".to_string(),
+                    new_rate: 0,
+                },
+            );
+
+            let mut pad = timeout(WAIT, pads.recv()).await.unwrap().unwrap();
+            let connected = timeout(WAIT, events.recv()).await.unwrap().unwrap();
+            assert!(matches!(connected, SerialEvent::Connect { device } if device == "pad"));
+
+            pad.write_all(b"log:{\"content\":\"hello\",\"level\":\"info\"};\n")
+                .await
+                .unwrap();
+            match timeout(WAIT, events.recv()).await.unwrap().unwrap() {
+                SerialEvent::Event { device, event, .. } => {
+                    assert_eq!(device, "pad");
+                    assert!(matches!(event, PadEvent::Log { content, .. } if content == "hello"));
+                }
+                _ => panic!("Expected the pad's log line"),
+            }
+
+            let command = Message {
+                message_type: MessageType::Command(CommandType::SetMode),
+                data: Some(json!({"mode": "work"})),
+                id: None,
+            };
+            let expected = create_message(command.clone()).unwrap();
+            send_serial_command(runtime.clone(), Some("pad".to_string()), command);
+            let mut written = vec![0u8; expected.len()];
+            timeout(WAIT, pad.read_exact(&mut written))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(String::from_utf8(written).unwrap(), expected);
+
+            drop(pad);
+            let disconnected = timeout(WAIT, events.recv()).await.unwrap().unwrap();
+            assert!(matches!(disconnected, SerialEvent::Disconnect { device } if device == "pad"));
+
+            send_listener_command(runtime.clone(), ListenerCommand::Quit);
+            let _ = remove_dir_all(runtime.config_dir());
+        }
+
+        #[tokio::test]
+        async fn reports_addresses_that_dont_parse() {
+            let (runtime, mut events) = listener("serial-bad");
+            send_listener_command(
+                runtime.clone(),
+                ListenerCommand::Connect {
+                    device: "pad".to_string(),
+                    new_port: "carrier-pigeon://coop".to_string(),
+                    new_rate: 0,
+                },
+            );
+
+            match timeout(WAIT, events.recv()).await.unwrap().unwrap() {
+                SerialEvent::Error { device, reason } => {
+                    assert_eq!(device, "pad");
+                    assert!(reason.contains("carrier-pigeon"));
+                }
+                _ => panic!("Expected a connection error"),
+            }
+            send_listener_command(runtime.clone(), ListenerCommand::Quit);
+            let _ = remove_dir_all(runtime.config_dir());
+        }
+
+        #[tokio::test]
+        async fn reports_commands_that_dont_parse() {
+            let (runtime, mut events) = listener("serial-garbled");
+            runtime.trigger("ratpad://serial/cmd", Some("{\"Teleport\":{}}".to_string()));
+
+            match timeout(WAIT, events.recv()).await.unwrap().unwrap() {
+                SerialEvent::Error { device, reason } => {
+                    assert!(device.is_empty());
+                    assert!(reason.starts_with("Invalid listener command"));
+                }
+                _ => panic!("Expected a command error"),
+            }
+            send_listener_command(runtime.clone(), ListenerCommand::Quit);
+            let _ = remove_dir_all(runtime.config_dir());
+        }
     }
 }
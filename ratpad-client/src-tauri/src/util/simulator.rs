@@ -0,0 +1,557 @@
+pub mod pad_simulator {
+    use std::{
+        fs::{read_to_string, write},
+        path::PathBuf,
+    };
+
+    use serde_json::{json, Value};
+
+    use crate::ratpad_communication::{
+        create_message, parse_message, ColorKey, ColorsConfig, CommandType, EventType, Key,
+        Message, MessageType, ModeConfig, PadConfig, PROTOCOL_VERSION,
+    };
+
+    pub const SIMULATOR_FIRMWARE: &str = "sim-0.1.0";
+
+    /// Physical key codes, numbered like `Keys` in the firmware's keymap.
+    pub const KEY_PREVIOUS: u32 = 0;
+    pub const KEY_SELECT: u32 = 1;
+    pub const KEY_NEXT: u32 = 2;
+    pub const KEY_COUNT: u32 = 12;
+
+    const PAGE_SIZE: usize = 9;
+
+    pub fn key_name(code: u32) -> String {
+        match code {
+            KEY_PREVIOUS => "previous".to_string(),
+            KEY_SELECT => "select".to_string(),
+            KEY_NEXT => "next".to_string(),
+            _ => format!("action_{}", code - 2),
+        }
+    }
+
+    /// The key code for a name like `next` or `action_4`, or a bare code.
+    pub fn key_code(name: &str) -> Option<u32> {
+        (0..KEY_COUNT)
+            .find(|code| key_name(*code) == name)
+            .or_else(|| name.parse::<u32>().ok().filter(|code| *code < KEY_COUNT))
+    }
+
+    fn default_config() -> PadConfig {
+        PadConfig {
+            colors: ColorsConfig {
+                next: (0, 0, 0),
+                previous: (0, 0, 0),
+                select: (0, 0, 0),
+                brightness: 1.0,
+            },
+            modes: Vec::new(),
+        }
+    }
+
+    fn event(data: Value) -> Message {
+        Message {
+            message_type: MessageType::Event(EventType::Event),
+            data: Some(data),
+            id: None,
+        }
+    }
+
+    fn log(content: String, level: &str) -> Message {
+        Message {
+            message_type: MessageType::Event(EventType::Log),
+            data: Some(json!({"content": content, "level": level})),
+            id: None,
+        }
+    }
+
+    /// A MacroPad running the ratpad firmware, minus the hardware. Mirrors `PadManager`: commands
+    /// and inputs go in, the messages the pad would write come out.
+    pub struct SimulatedPad {
+        db: Option<PathBuf>,
+        config: PadConfig,
+        mode: Option<String>,
+        last_mode: Option<String>,
+        page: usize,
+        encoder: i32,
+        encoder_switch: bool,
+        typed: Vec<String>,
+    }
+
+    impl SimulatedPad {
+        /// Loads the pad's state from `db` (the firmware's `db.json`), creating it if missing.
+        /// Without a path nothing is persisted.
+        pub fn new(db: Option<PathBuf>) -> SimulatedPad {
+            let config = db
+                .as_ref()
+                .and_then(|path| read_to_string(path).ok())
+                .and_then(|data| serde_json::from_str::<PadConfig>(&data).ok())
+                .unwrap_or_else(default_config);
+
+            let pad = SimulatedPad {
+                db,
+                config,
+                mode: None,
+                last_mode: None,
+                page: 0,
+                encoder: 0,
+                encoder_switch: false,
+                typed: Vec::new(),
+            };
+            pad.save();
+            pad
+        }
+
+        pub fn config(&self) -> &PadConfig {
+            &self.config
+        }
+
+        pub fn mode(&self) -> Option<String> {
+            self.mode.clone()
+        }
+
+        /// What the client had the pad type, oldest first: chords as sent, `<keys> down` and
+        /// `<keys> up` for held keys (`all up` to release everything), `click <button>` and
+        /// `mouse <x> <y> <wheel>`.
+        pub fn typed(&self) -> &[String] {
+            &self.typed
+        }
+
+        fn save(&self) {
+            if let (Some(path), Ok(data)) = (&self.db, serde_json::to_string(&self.config)) {
+                let _ = write(path, data);
+            }
+        }
+
+        fn find_mode(&self, key: &str) -> Option<&ModeConfig> {
+            self.config.modes.iter().find(|m| m.key == key)
+        }
+
+        fn mode_index(&self) -> Option<usize> {
+            let current = self.mode.as_ref()?;
+            self.config.modes.iter().position(|m| &m.key == current)
+        }
+
+        fn set_mode(&mut self, mode: Option<String>) -> Message {
+            if self.mode != mode {
+                self.last_mode = self.mode.take();
+                self.mode = mode;
+            }
+            event(json!({"mode": self.mode, "type": "mode", "encoder": self.encoder}))
+        }
+
+        pub fn connect(&self) -> Vec<Message> {
+            vec![
+                Message {
+                    message_type: MessageType::Event(EventType::Connect),
+                    data: None,
+                    id: None,
+                },
+                log("System connected.".to_string(), "info"),
+            ]
+        }
+
+        pub fn disconnect(&self) -> Vec<Message> {
+            vec![Message {
+                message_type: MessageType::Event(EventType::Disconnect),
+                data: None,
+                id: None,
+            }]
+        }
+
+        fn capabilities(&self) -> Value {
+            json!({
+                "firmware": SIMULATOR_FIRMWARE,
+                "protocol": PROTOCOL_VERSION,
+                "commands": [
+                    "hello",
+                    "set_color",
+                    "write_mode",
+                    "delete_mode",
+                    "clear_modes",
+                    "set_mode",
+                    "set_home",
+                    "read_config",
+                    "send_keys",
+                    "send_sequence",
+                    "send_mouse",
+                ],
+                "keys": KEY_COUNT - 3,
+                "encoder": self.encoder,
+            })
+        }
+
+        fn run_command(
+            &mut self,
+            command: CommandType,
+            data: Option<Value>,
+            id: Option<u64>,
+        ) -> Result<Vec<Message>, String> {
+            let data = data.unwrap_or(Value::Null);
+            let mut out = Vec::new();
+
+            match command {
+                CommandType::Hello => {
+                    if data.get("protocol").and_then(|p| p.as_u64())
+                        != Some(PROTOCOL_VERSION as u64)
+                    {
+                        out.push(log(
+                            format!(
+                                "Client protocol {} differs from firmware protocol {PROTOCOL_VERSION}",
+                                data.get("protocol").unwrap_or(&Value::Null)
+                            ),
+                            "warning",
+                        ));
+                    }
+                    out.push(Message {
+                        message_type: MessageType::Event(EventType::Capabilities),
+                        data: Some(self.capabilities()),
+                        id,
+                    });
+                }
+                CommandType::SetColor => {
+                    let key = serde_json::from_value::<ColorKey>(
+                        data.get("key").cloned().unwrap_or_default(),
+                    )
+                    .map_err(|e| e.to_string())?;
+                    let color = data.get("color").cloned().ok_or("Missing color")?;
+                    let colors = &mut self.config.colors;
+                    match key {
+                        ColorKey::Next => {
+                            colors.next =
+                                serde_json::from_value(color).map_err(|e| e.to_string())?
+                        }
+                        ColorKey::Previous => {
+                            colors.previous =
+                                serde_json::from_value(color).map_err(|e| e.to_string())?
+                        }
+                        ColorKey::Select => {
+                            colors.select =
+                                serde_json::from_value(color).map_err(|e| e.to_string())?
+                        }
+                        ColorKey::Brightness => {
+                            colors.brightness =
+                                serde_json::from_value(color).map_err(|e| e.to_string())?
+                        }
+                    }
+                    self.save();
+                    out.push(log(format!("Set color [{}]", data["key"]), "debug"));
+                }
+                CommandType::WriteMode => {
+                    let mode =
+                        serde_json::from_value::<ModeConfig>(data).map_err(|e| e.to_string())?;
+                    let key = mode.key.clone();
+                    match self.config.modes.iter().position(|m| m.key == key) {
+                        Some(index) => self.config.modes[index] = mode,
+                        None => self.config.modes.push(mode),
+                    }
+                    self.save();
+                    out.push(log(format!("Written mode: {key}"), "debug"));
+                }
+                CommandType::DeleteMode => {
+                    let key = data
+                        .get("key")
+                        .and_then(|k| k.as_str())
+                        .ok_or("Missing key")?
+                        .to_string();
+                    if self.mode.as_ref() == Some(&key) {
+                        self.mode = None;
+                    }
+                    self.config.modes.retain(|m| m.key != key);
+                    self.save();
+                    out.push(log(format!("Removed mode: {key}"), "debug"));
+                }
+                CommandType::ClearModes => {
+                    self.mode = None;
+                    self.config.modes.clear();
+                    self.save();
+                    out.push(log("Cleared modes".to_string(), "debug"));
+                }
+                CommandType::SetMode => {
+                    if let Some(key) = data.get("mode").and_then(|m| m.as_str()) {
+                        if self.find_mode(key).is_some() {
+                            out.push(self.set_mode(Some(key.to_string())));
+                        }
+                    }
+                }
+                CommandType::SetHome => out.push(self.set_mode(None)),
+                CommandType::SendKeys => {
+                    let keys = data.get("keys").and_then(|k| k.as_str());
+                    let typed = match (data.get("action").and_then(|a| a.as_str()), keys) {
+                        (Some("press"), Some(keys)) => format!("{keys} down"),
+                        (Some("release"), Some(keys)) => format!("{keys} up"),
+                        (Some("release"), None) => "all up".to_string(),
+                        (_, Some(keys)) => keys.to_string(),
+                        (_, None) => return Err("Missing keys".to_string()),
+                    };
+                    self.typed.push(typed);
+                }
+                CommandType::SendSequence => {
+                    let chords = serde_json::from_value::<Vec<String>>(
+                        data.get("chords").cloned().unwrap_or_default(),
+                    )
+                    .map_err(|e| e.to_string())?;
+                    self.typed.extend(chords);
+                }
+                CommandType::SendMouse => {
+                    let typed = match data.get("click").and_then(|c| c.as_str()) {
+                        Some(button) => format!("click {button}"),
+                        None => format!(
+                            "mouse {} {} {}",
+                            data.get("x").and_then(|v| v.as_i64()).unwrap_or(0),
+                            data.get("y").and_then(|v| v.as_i64()).unwrap_or(0),
+                            data.get("wheel").and_then(|v| v.as_i64()).unwrap_or(0)
+                        ),
+                    };
+                    self.typed.push(typed);
+                }
+                CommandType::ReadConfig => out.push(Message {
+                    message_type: MessageType::Event(EventType::Config),
+                    data: serde_json::to_value(&self.config).ok(),
+                    id,
+                }),
+            }
+            Ok(out)
+        }
+
+        /// Handles one line from the client. Errors are reported the way the firmware does, as an
+        /// `error` log.
+        pub fn handle_line(&mut self, line: &str) -> Vec<Message> {
+            let Some(message) = parse_message(line.to_string()) else {
+                return Vec::new();
+            };
+            let MessageType::Command(command) = message.message_type.clone() else {
+                return Vec::new();
+            };
+
+            let name = serde_json::to_value(&message.message_type).unwrap_or_default();
+            let mut out = vec![log(
+                format!("Parsing command: {}", name.as_str().unwrap_or_default()),
+                "debug",
+            )];
+            match self.run_command(command, message.data, message.id) {
+                Ok(replies) => out.extend(replies),
+                Err(error) => out.push(log(error, "error")),
+            }
+            out
+        }
+
+        fn page_modes(&self) -> &[ModeConfig] {
+            let start = (self.page * PAGE_SIZE).min(self.config.modes.len());
+            let end = (start + PAGE_SIZE).min(self.config.modes.len());
+            &self.config.modes[start..end]
+        }
+
+        /// Presses the key with this physical code, following the firmware's navigation rules.
+        pub fn press(&mut self, code: u32) -> Vec<Message> {
+            if code >= KEY_COUNT {
+                return Vec::new();
+            }
+            let modes = self.config.modes.len();
+
+            if code <= KEY_NEXT {
+                if let Some(index) = self.mode_index() {
+                    let target = match code {
+                        KEY_PREVIOUS => {
+                            Some(self.config.modes[(index + modes - 1) % modes].key.clone())
+                        }
+                        KEY_NEXT => Some(self.config.modes[(index + 1) % modes].key.clone()),
+                        _ => None,
+                    };
+                    return vec![self.set_mode(target)];
+                }
+
+                match code {
+                    KEY_PREVIOUS => {
+                        self.page = if self.page == 0 {
+                            modes / PAGE_SIZE
+                        } else {
+                            self.page - 1
+                        };
+                    }
+                    KEY_NEXT => {
+                        self.page += 1;
+                        if self.page_modes().is_empty() {
+                            self.page = 0;
+                        }
+                    }
+                    _ => {
+                        let last = self
+                            .last_mode
+                            .clone()
+                            .filter(|key| self.find_mode(key).is_some());
+                        return vec![self.set_mode(last)];
+                    }
+                }
+                return vec![event(
+                    json!({"mode": self.mode, "type": "mode", "encoder": self.encoder}),
+                )];
+            }
+
+            let action = (code - 3) as usize;
+            if self.mode.is_some() {
+                let key = Key {
+                    code,
+                    name: key_name(code),
+                    action: action as i32,
+                };
+                vec![event(json!({"mode": self.mode, "type": "key", "key": key}))]
+            } else if let Some(mode) = self.page_modes().get(action).map(|m| m.key.clone()) {
+                vec![self.set_mode(Some(mode))]
+            } else {
+                Vec::new()
+            }
+        }
+
+        /// Turns the encoder. Like the firmware, nothing is reported outside a mode.
+        pub fn rotate(&mut self, delta: i32) -> Vec<Message> {
+            self.encoder += delta;
+            if self.mode.is_none() || delta == 0 {
+                return Vec::new();
+            }
+            vec![event(
+                json!({"mode": self.mode, "type": "encoder.value", "value": self.encoder}),
+            )]
+        }
+
+        pub fn set_encoder_switch(&mut self, pressed: bool) -> Vec<Message> {
+            if self.encoder_switch == pressed {
+                return Vec::new();
+            }
+            self.encoder_switch = pressed;
+            if self.mode.is_none() {
+                return Vec::new();
+            }
+            vec![event(
+                json!({"mode": self.mode, "type": "encoder.switch", "pressed": pressed}),
+            )]
+        }
+    }
+
+    /// Frames messages for the wire. The firmware upper-cases its headers, so this does too.
+    pub fn encode(messages: Vec<Message>) -> String {
+        messages
+            .into_iter()
+            .filter_map(|message| create_message(message).ok())
+            .map(|line| match line.split_once(':') {
+                Some((header, rest)) => format!("{}:{rest}", header.to_uppercase()),
+                None => line,
+            })
+            .collect()
+    }
+
+    #[cfg(unix)]
+    mod pty {
+        use std::{
+            fs::{remove_file, File},
+            io::{self, BufRead, BufReader, Write},
+            os::{fd::OwnedFd, unix::fs::symlink},
+            path::{Path, PathBuf},
+            sync::{Arc, Mutex},
+            thread,
+        };
+
+        use nix::{
+            pty::openpty,
+            sys::termios::{cfmakeraw, tcgetattr, tcsetattr, SetArg},
+            unistd::ttyname,
+        };
+
+        use super::{encode, SimulatedPad};
+        use crate::ratpad_communication::Message;
+
+        /// A [`SimulatedPad`] attached to a pseudo-terminal, so the client can open it like a real port.
+        pub struct PtyPad {
+            pad: Arc<Mutex<SimulatedPad>>,
+            writer: Arc<Mutex<File>>,
+            path: PathBuf,
+            link: Option<PathBuf>,
+            // Held open so the master doesn't see a hangup between client connections.
+            _slave: OwnedFd,
+        }
+
+        impl PtyPad {
+            /// Opens a pty for `pad`, optionally symlinked at `link`, and starts answering commands.
+            pub fn open(pad: SimulatedPad, link: Option<PathBuf>) -> io::Result<PtyPad> {
+                let pty = openpty(None, None)?;
+                let mut termios = tcgetattr(&pty.slave)?;
+                cfmakeraw(&mut termios);
+                tcsetattr(&pty.slave, SetArg::TCSANOW, &termios)?;
+                let path = ttyname(&pty.slave)?;
+
+                if let Some(link_path) = &link {
+                    if link_path.symlink_metadata().is_ok() {
+                        remove_file(link_path)?;
+                    }
+                    symlink(&path, link_path)?;
+                }
+
+                let master = File::from(pty.master);
+                let reader = master.try_clone()?;
+                let simulator = PtyPad {
+                    pad: Arc::new(Mutex::new(pad)),
+                    writer: Arc::new(Mutex::new(master)),
+                    path,
+                    link,
+                    _slave: pty.slave,
+                };
+
+                let pad = simulator.pad.clone();
+                let writer = simulator.writer.clone();
+                thread::spawn(move || {
+                    for line in BufReader::new(reader).lines() {
+                        let Ok(line) = line else { break };
+                        let replies = match pad.lock() {
+                            Ok(mut pad) => pad.handle_line(&line),
+                            Err(_) => break,
+                        };
+                        write_messages(&writer, replies);
+                    }
+                });
+
+                simulator.send(|pad| pad.connect());
+                Ok(simulator)
+            }
+
+            /// The pty device the client should connect to.
+            pub fn path(&self) -> &Path {
+                self.link.as_deref().unwrap_or(&self.path)
+            }
+
+            /// Runs `input` against the pad and writes whatever it produces to the client.
+            pub fn send<F: FnOnce(&mut SimulatedPad) -> Vec<Message>>(&self, input: F) {
+                let messages = match self.pad.lock() {
+                    Ok(mut pad) => input(&mut pad),
+                    Err(_) => return,
+                };
+                write_messages(&self.writer, messages);
+            }
+
+            pub fn with_pad<R, F: FnOnce(&SimulatedPad) -> R>(&self, read: F) -> Option<R> {
+                self.pad.lock().ok().map(|pad| read(&pad))
+            }
+        }
+
+        impl Drop for PtyPad {
+            fn drop(&mut self) {
+                self.send(|pad| pad.disconnect());
+                if let Some(link) = &self.link {
+                    let _ = remove_file(link);
+                }
+            }
+        }
+
+        fn write_messages(writer: &Arc<Mutex<File>>, messages: Vec<Message>) {
+            if messages.is_empty() {
+                return;
+            }
+            if let Ok(mut file) = writer.lock() {
+                let _ = file.write_all(encode(messages).as_bytes());
+                let _ = file.flush();
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    pub use pty::PtyPad;
+}
@@ -1,55 +1,128 @@
 pub mod app_state {
-    use std::sync::{Mutex, MutexGuard};
+    use std::{
+        collections::HashMap,
+        sync::{Mutex, MutexGuard},
+    };
 
     use serde::{Deserialize, Serialize};
 
-    use crate::util::configuration::AppConfig;
+    use crate::{
+        ratpad_communication::PadCapabilities,
+        util::{
+            config_journal::ConfigJournal, config_store::ConfigEvent, configuration::AppConfig,
+            request_router::RequestTable,
+        },
+    };
 
-    #[derive(Serialize, Deserialize, Clone)]
+    #[derive(Serialize, Deserialize, Clone, PartialEq)]
     pub enum ConnectionState {
         Connected,
         Waiting,
-        Disconnected
+        Disconnected,
+    }
+
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct DeviceState {
+        pub device: String,
+        pub connection: ConnectionState,
+        pub port: Option<String>,
+        pub rate: Option<u32>,
+        pub capabilities: Option<PadCapabilities>,
+    }
+
+    impl DeviceState {
+        fn new(device: String) -> DeviceState {
+            DeviceState {
+                device,
+                connection: ConnectionState::Disconnected,
+                port: None,
+                rate: None,
+                capabilities: None,
+            }
+        }
     }
 
     pub struct ApplicationState {
-        pub connection: Mutex<ConnectionState>,
-        pub port: Mutex<Option<String>>,
-        pub rate: Mutex<Option<u32>>,
-        pub config: Mutex<AppConfig>
+        pub devices: Mutex<HashMap<String, DeviceState>>,
+        pub config: Mutex<AppConfig>,
+        pub requests: Mutex<RequestTable>,
+
+        /// The last problem loading or saving the config.
+        pub config_event: Mutex<Option<ConfigEvent>>,
+
+        /// Undo and redo history. Always locked after `config`, never before.
+        pub journal: Mutex<ConfigJournal>,
     }
 
     impl ApplicationState {
-        pub fn set(&self, connection: ConnectionState, new_port: Option<String>, new_rate: Option<u32>) -> () {
-            if let Ok(mut conn) = self.connection.lock() {
-                *conn = connection;
+        fn update_device<F: FnOnce(&mut DeviceState)>(&self, device: String, update: F) {
+            if let Ok(mut devices) = self.devices.lock() {
+                update(
+                    devices
+                        .entry(device.clone())
+                        .or_insert_with(|| DeviceState::new(device)),
+                );
             }
+        }
 
-            if let Ok(mut port) = self.port.lock() {
-                *port = new_port;
-            }
+        pub fn set(
+            &self,
+            device: String,
+            connection: ConnectionState,
+            new_port: Option<String>,
+            new_rate: Option<u32>,
+        ) {
+            self.update_device(device, |state| {
+                state.connection = connection;
+                state.port = new_port;
+                state.rate = new_rate;
+            });
+        }
 
-            if let Ok(mut rate) = self.rate.lock() {
-                *rate = new_rate;
-            }
+        pub fn set_connection_state(&self, device: String, connection: ConnectionState) {
+            self.update_device(device, |state| state.connection = connection);
         }
 
-        pub fn set_connection_state(&self, connection: ConnectionState) -> () {
-            if let Ok(mut conn) = self.connection.lock() {
-                *conn = connection;
-            }
+        pub fn set_capabilities(&self, device: String, new_capabilities: Option<PadCapabilities>) {
+            self.update_device(device, |state| state.capabilities = new_capabilities);
         }
 
-        pub fn set_connection_parameters(&self, new_port: Option<String>, new_rate: Option<u32>) -> () {
-            if let Ok(mut port) = self.port.lock() {
-                *port = new_port;
+        pub fn device(&self, device: &str) -> Option<DeviceState> {
+            if let Ok(devices) = self.devices.lock() {
+                devices.get(device).cloned()
+            } else {
+                None
             }
+        }
 
-            if let Ok(mut rate) = self.rate.lock() {
-                *rate = new_rate;
+        pub fn capabilities(&self, device: &str) -> Option<PadCapabilities> {
+            self.device(device).and_then(|state| state.capabilities)
+        }
+
+        /// Every known device, ordered by id.
+        pub fn devices(&self) -> Vec<DeviceState> {
+            if let Ok(devices) = self.devices.lock() {
+                let mut result: Vec<DeviceState> = devices.values().cloned().collect();
+                result.sort_by(|a, b| a.device.cmp(&b.device));
+                result
+            } else {
+                Vec::new()
             }
         }
 
+        pub fn connected_devices(&self) -> Vec<String> {
+            self.devices()
+                .into_iter()
+                .filter(|state| state.connection == ConnectionState::Connected)
+                .map(|state| state.device)
+                .collect()
+        }
+
+        /// Picks the explicit target if given, otherwise the first connected device.
+        pub fn resolve_device(&self, device: Option<String>) -> Option<String> {
+            device.or_else(|| self.connected_devices().into_iter().next())
+        }
+
         pub fn lock_config(&self) -> Option<MutexGuard<AppConfig>> {
             if let Ok(conf) = self.config.lock() {
                 Some(conf)
@@ -57,5 +130,13 @@ pub mod app_state {
                 None
             }
         }
+
+        pub fn lock_journal(&self) -> Option<MutexGuard<'_, ConfigJournal>> {
+            self.journal.lock().ok()
+        }
+
+        pub fn lock_requests(&self) -> Option<MutexGuard<'_, RequestTable>> {
+            self.requests.lock().ok()
+        }
     }
-}
\ No newline at end of file
+}
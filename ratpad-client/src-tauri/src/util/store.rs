@@ -0,0 +1,409 @@
+pub mod config_store {
+    use std::{
+        cmp::Reverse,
+        collections::BTreeMap,
+        fs::{copy, create_dir_all, read_dir, read_to_string, remove_file, rename, File},
+        io::{self, ErrorKind, Write},
+        path::{Path, PathBuf},
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use serde::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    use crate::util::{
+        config_format::{config_path, ConfigFormat},
+        config_sync::ConfigDiff,
+        configuration::{document_version, migrate, AppConfig, SCHEMA_VERSION},
+        pad_runtime::PadRuntime,
+    };
+
+    /// Where backups live, inside the config directory.
+    pub const BACKUP_DIR: &str = "backups";
+
+    /// Backups kept before the oldest are deleted.
+    pub const MAX_BACKUPS: usize = 10;
+
+    /// Saves closer together than this share a backup, so a burst of edits doesn't push older,
+    /// known good configs out of the rotation.
+    pub const BACKUP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+    /// Sent on "ratpad://config" when the config file changes on disk, or couldn't be used or
+    /// written.
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    #[serde(tag = "type")]
+    pub enum ConfigEvent {
+        /// The config file was unreadable and the newest valid backup was loaded instead.
+        #[serde(rename = "recovered")]
+        Recovered {
+            backup: String,
+            reason: String,
+            /// Where the unreadable file was moved to.
+            corrupt: Option<String>,
+        },
+
+        /// Neither the config file nor any backup was readable, so the defaults were loaded.
+        #[serde(rename = "reset")]
+        Reset {
+            reason: String,
+            corrupt: Option<String>,
+        },
+
+        /// A file the config includes couldn't be used, so the config was loaded without it. The
+        /// config isn't saved until every include loads again.
+        #[serde(rename = "include_failed")]
+        IncludeFailed { reason: String },
+
+        #[serde(rename = "save_failed")]
+        SaveFailed { reason: String },
+
+        /// The previous file couldn't be copied into the backups. It was saved over regardless.
+        #[serde(rename = "backup_failed")]
+        BackupFailed { reason: String },
+
+        /// The file was edited outside the client and loaded. `diff` has what changed for the
+        /// pads, with the new settings as `client` and the old ones as `pad`.
+        #[serde(rename = "reloaded")]
+        Reloaded { diff: ConfigDiff },
+
+        /// The file was edited outside the client but is invalid, so the running config was kept.
+        #[serde(rename = "reload_failed")]
+        ReloadFailed { reason: String },
+    }
+
+    fn timestamp() -> u128 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default()
+    }
+
+    /// Replaces `path` with `data` so that a crash leaves either the old file or the new one,
+    /// never a mix: the data is written and flushed to a temporary file first, then renamed over.
+    pub fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        let temporary = PathBuf::from(temporary);
+
+        let mut file = File::create(&temporary)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        rename(&temporary, path)?;
+
+        // The rename itself only survives a crash once the directory is flushed too.
+        #[cfg(unix)]
+        if let Some(dir) = path.parent() {
+            File::open(dir)?.sync_all()?;
+        }
+        Ok(())
+    }
+
+    fn format_of(path: &Path) -> Result<ConfigFormat, String> {
+        ConfigFormat::from_path(path)
+            .ok_or_else(|| format!("Unknown config format: {}", path.display()))
+    }
+
+    fn read_document(path: &Path) -> Result<Value, String> {
+        let data =
+            read_to_string(path).map_err(|e| format!("Unable to read {}: {e}", path.display()))?;
+        format_of(path)?
+            .parse(&data)
+            .map_err(|e| format!("Unable to parse {}: {e}", path.display()))
+    }
+
+    /// Adds the modes from every file the document's `include` globs match, relative to `dir`,
+    /// returning which file each came from and why any were left out. A mode the config itself
+    /// already has wins.
+    fn read_includes(document: &mut Value, dir: &Path) -> (BTreeMap<String, PathBuf>, Vec<String>) {
+        let patterns: Vec<String> = document
+            .get("include")
+            .and_then(|include| serde_json::from_value(include.clone()).ok())
+            .unwrap_or_default();
+
+        let mut included = Vec::new();
+        let mut errors = Vec::new();
+        for pattern in patterns {
+            let full = dir.join(&pattern);
+            let paths = match glob::glob(&full.to_string_lossy()) {
+                Ok(paths) => paths,
+                Err(error) => {
+                    errors.push(format!("Invalid include {pattern}: {error}"));
+                    continue;
+                }
+            };
+            for path in paths.filter_map(Result::ok) {
+                match read_document(&path) {
+                    Ok(mode) => included.push((mode, path)),
+                    Err(reason) => errors.push(reason),
+                }
+            }
+        }
+
+        let mut sources = BTreeMap::new();
+        if let Some(modes) = document.get_mut("modes").and_then(Value::as_array_mut) {
+            for (mode, path) in included {
+                let Some(key) = mode.get("key").and_then(Value::as_str).map(str::to_string) else {
+                    errors.push(format!("{} has no mode key", path.display()));
+                    continue;
+                };
+                if modes
+                    .iter()
+                    .any(|m| m.get("key").and_then(Value::as_str) == Some(&key))
+                {
+                    errors.push(format!(
+                        "Ignoring {}: mode {key} is already in the config",
+                        path.display()
+                    ));
+                    continue;
+                }
+                modes.push(mode);
+                sources.insert(key, path);
+            }
+        }
+        (sources, errors)
+    }
+
+    /// Reads and migrates a config file, along with the modes it includes from `dir`, returning
+    /// the schema version it was written with.
+    pub fn read_config(path: &Path, dir: &Path) -> Result<(AppConfig, u32), String> {
+        let document = read_document(path)?;
+        let version = document_version(&document);
+        let mut document = migrate(document);
+        let (sources, include_errors) = read_includes(&mut document, dir);
+        let mut config = AppConfig::from_document(document)
+            .map_err(|e| format!("Unable to parse {}: {e}", path.display()))?;
+        config.sources = sources;
+        config.include_errors = include_errors;
+        Ok((config, version))
+    }
+
+    /// Keeps a copy of a config file about to be migrated, as `backups/config-v<version>.<ext>`.
+    /// Only the first copy for each version is kept, and these aren't rotated.
+    fn keep_before_migration(dir: &Path, path: &Path, version: u32) -> io::Result<()> {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("json");
+        let target = dir
+            .join(BACKUP_DIR)
+            .join(format!("config-v{version}.{extension}"));
+        if !target.exists() {
+            create_dir_all(dir.join(BACKUP_DIR))?;
+            copy(path, target)?;
+        }
+        Ok(())
+    }
+
+    /// Backups in `dir`, newest first. Only the config file itself is backed up, not the files
+    /// it includes.
+    pub fn backups(dir: &Path) -> Vec<PathBuf> {
+        let Ok(entries) = read_dir(dir.join(BACKUP_DIR)) else {
+            return Vec::new();
+        };
+        let mut found: Vec<(u128, PathBuf)> = entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter_map(|path| {
+                let name = path.file_name()?.to_str()?;
+                ConfigFormat::from_path(&path)?;
+                let stamp = name
+                    .strip_prefix("config-")?
+                    .split_once('.')?
+                    .0
+                    .parse()
+                    .ok()?;
+                Some((stamp, path))
+            })
+            .collect();
+        found.sort_by_key(|(stamp, _)| Reverse(*stamp));
+        found.into_iter().map(|(_, path)| path).collect()
+    }
+
+    /// Copies the current config file into the backups if the newest backup is older than
+    /// [`BACKUP_INTERVAL`], then deletes all but the newest [`MAX_BACKUPS`]. The file is copied
+    /// as it is; recovery skips any backup that doesn't load.
+    pub fn back_up(dir: &Path) -> io::Result<()> {
+        let current = config_path(dir);
+        if !current.exists() {
+            return Ok(());
+        }
+
+        let existing = backups(dir);
+        let recent = existing
+            .first()
+            .and_then(|newest| newest.metadata().ok()?.modified().ok()?.elapsed().ok())
+            .is_some_and(|age| age < BACKUP_INTERVAL);
+        if recent {
+            return Ok(());
+        }
+
+        create_dir_all(dir.join(BACKUP_DIR))?;
+        let extension = current
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("json");
+        copy(
+            &current,
+            dir.join(BACKUP_DIR)
+                .join(format!("config-{}.{extension}", timestamp())),
+        )?;
+
+        for old in backups(dir).into_iter().skip(MAX_BACKUPS) {
+            remove_file(old)?;
+        }
+        Ok(())
+    }
+
+    /// Writes `document` to `path` in the format its extension names, keeping what it can of the
+    /// file already there. Files that wouldn't change aren't touched.
+    fn write_document(path: &Path, document: &Value, mode: bool) -> Result<(), String> {
+        let format = format_of(path)?;
+        let previous = read_to_string(path).ok();
+        let data = if mode {
+            format.render_mode(document, previous.as_deref())
+        } else {
+            format.render(document, previous.as_deref())
+        }
+        .map_err(|e| format!("Unable to serialize {}: {e}", path.display()))?;
+
+        if previous.as_deref() == Some(data.as_str()) {
+            return Ok(());
+        }
+        write_atomic(path, data.as_bytes())
+            .map_err(|e| format!("Unable to write {}: {e}", path.display()))
+    }
+
+    /// Writes included modes back to their own files and takes them out of `document`. Files
+    /// of modes that are gone from every profile are deleted, as they'd be loaded again otherwise.
+    fn write_includes(config: &AppConfig, document: &mut Value) -> Result<(), String> {
+        if let Some(modes) = document.get_mut("modes").and_then(Value::as_array_mut) {
+            let mut kept = Vec::new();
+            for mode in modes.drain(..) {
+                match mode
+                    .get("key")
+                    .and_then(Value::as_str)
+                    .and_then(|key| config.sources.get(key))
+                {
+                    Some(path) => write_document(path, &mode, true)?,
+                    None => kept.push(mode),
+                }
+            }
+            *modes = kept;
+        }
+
+        for (key, path) in config.sources.iter() {
+            let known = config
+                .modes
+                .iter()
+                .chain(config.profiles.values().flat_map(|p| p.modes.iter()));
+            if known.map(|m| m.key()).all(|k| k != *key) {
+                match remove_file(path) {
+                    Err(error) if error.kind() != ErrorKind::NotFound => {
+                        return Err(format!("Unable to delete {}: {error}", path.display()))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes `config` to `dir`, in the format of the config file already there.
+    pub fn save_config(dir: &Path, config: &AppConfig) -> Result<(), String> {
+        save_config_as(dir, &config_path(dir), config)
+    }
+
+    fn save_config_as(dir: &Path, path: &Path, config: &AppConfig) -> Result<(), String> {
+        create_dir_all(dir).map_err(|e| format!("Unable to create config directory: {e}"))?;
+        let mut document =
+            serde_json::to_value(config).map_err(|e| format!("Unable to serialize config: {e}"))?;
+        write_includes(config, &mut document)?;
+        write_document(path, &document, false)
+    }
+
+    /// Rewrites the config file in another format. The old file is moved into the backups, and
+    /// included files are left as they are.
+    pub fn convert_config(
+        dir: &Path,
+        config: &AppConfig,
+        format: ConfigFormat,
+    ) -> Result<PathBuf, String> {
+        let current = config_path(dir);
+        let target = dir.join(format!("config.{}", format.extension()));
+        if current != target && current.exists() {
+            create_dir_all(dir.join(BACKUP_DIR))
+                .map_err(|e| format!("Unable to create backup directory: {e}"))?;
+            let aside = dir.join(BACKUP_DIR).join(format!(
+                "config-{}.{}",
+                timestamp(),
+                current
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .unwrap_or("json")
+            ));
+            rename(&current, aside)
+                .map_err(|e| format!("Unable to move {} aside: {e}", current.display()))?;
+        }
+        save_config_as(dir, &target, config)?;
+        Ok(target)
+    }
+
+    /// Loads the config from `dir`, from whichever format of config file is there. `None` means
+    /// there is no config file yet. When the file can't be used it is moved aside and the newest
+    /// readable backup, or the defaults, are returned along with what happened.
+    pub fn load_config(dir: &Path) -> Option<(AppConfig, Vec<ConfigEvent>)> {
+        let path = config_path(dir);
+        if !path.exists() {
+            return None;
+        }
+
+        let reason = match read_config(&path, dir) {
+            Ok((config, version)) => {
+                let mut events: Vec<ConfigEvent> = config
+                    .include_errors
+                    .iter()
+                    .map(|reason| ConfigEvent::IncludeFailed {
+                        reason: reason.clone(),
+                    })
+                    .collect();
+                if version < SCHEMA_VERSION {
+                    if let Err(error) = keep_before_migration(dir, &path, version) {
+                        events.push(ConfigEvent::BackupFailed {
+                            reason: format!(
+                                "Unable to back up config before migrating it: {error}"
+                            ),
+                        });
+                    }
+                }
+                return Some((config, events));
+            }
+            Err(reason) => reason,
+        };
+
+        let mut aside = path.clone().into_os_string();
+        aside.push(format!(".corrupt-{}", timestamp()));
+        let aside = PathBuf::from(aside);
+        let corrupt = rename(&path, &aside)
+            .ok()
+            .map(|_| aside.display().to_string());
+
+        for backup in backups(dir) {
+            if let Ok((config, _)) = read_config(&backup, dir) {
+                let event = ConfigEvent::Recovered {
+                    backup: backup.display().to_string(),
+                    reason,
+                    corrupt,
+                };
+                return Some((config, vec![event]));
+            }
+        }
+        Some((
+            AppConfig::default(),
+            vec![ConfigEvent::Reset { reason, corrupt }],
+        ))
+    }
+
+    /// Tells the front end, and keeps the event around for `config.last_event`.
+    pub fn report(app: &PadRuntime, event: ConfigEvent) {
+        if let Ok(mut last) = app.state().config_event.lock() {
+            *last = Some(event.clone());
+        }
+        app.emit("ratpad://config", event);
+    }
+}
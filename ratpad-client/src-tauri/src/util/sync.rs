@@ -0,0 +1,328 @@
+pub mod config_sync {
+    use std::collections::HashMap;
+
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+    use tokio::task::spawn_blocking;
+
+    use crate::{
+        ratpad_communication::{
+            ColorKey, ColorsConfig, CommandType, Message, MessageType, ModeConfig, PadConfig,
+        },
+        serial_client::send_serial_command,
+        util::{
+            command_handler::{fetch_pad_config, SetColorType},
+            configuration::{AppConfig, AppModeConfig, ConfigEdit, PadCompat, SyncPolicy},
+            pad_handshake::CapabilitiesEvent,
+            pad_runtime::PadRuntime,
+        },
+    };
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct ColorDiff {
+        pub key: ColorKey,
+        pub client: SetColorType,
+        pub pad: SetColorType,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    #[serde(tag = "change")]
+    pub enum ModeDiff {
+        #[serde(rename = "client_only")]
+        ClientOnly { key: String, client: ModeConfig },
+
+        #[serde(rename = "pad_only")]
+        PadOnly { key: String, pad: ModeConfig },
+
+        #[serde(rename = "changed")]
+        Changed {
+            key: String,
+            client: ModeConfig,
+            pad: ModeConfig,
+        },
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct ConfigDiff {
+        pub colors: Vec<ColorDiff>,
+        pub modes: Vec<ModeDiff>,
+    }
+
+    impl ConfigDiff {
+        pub fn is_empty(&self) -> bool {
+            self.colors.is_empty() && self.modes.is_empty()
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct SyncEvent {
+        pub device: String,
+        pub policy: SyncPolicy,
+        pub diff: ConfigDiff,
+        pub applied: bool,
+    }
+
+    /// Sent on "ratpad://sync/failed" when a pad couldn't be reconciled as it connected.
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct SyncFailedEvent {
+        pub device: String,
+        pub reason: String,
+    }
+
+    fn color_setting(colors: &ColorsConfig, key: &ColorKey) -> SetColorType {
+        match key {
+            ColorKey::Next => SetColorType::Next { color: colors.next },
+            ColorKey::Previous => SetColorType::Previous {
+                color: colors.previous,
+            },
+            ColorKey::Select => SetColorType::Select {
+                color: colors.select,
+            },
+            ColorKey::Brightness => SetColorType::Brightness {
+                color: colors.brightness,
+            },
+        }
+    }
+
+    /// Compares the flattened local config against the pad's, ignoring mode order.
+    pub fn compute_diff(client: &AppConfig, pad: &PadConfig) -> ConfigDiff {
+        let local = client.to_pad();
+
+        let colors = [
+            ColorKey::Next,
+            ColorKey::Previous,
+            ColorKey::Select,
+            ColorKey::Brightness,
+        ]
+        .iter()
+        .filter_map(|key| {
+            let client_color = color_setting(&local.colors, key);
+            let pad_color = color_setting(&pad.colors, key);
+            if serde_json::to_value(&client_color).ok() != serde_json::to_value(&pad_color).ok() {
+                Some(ColorDiff {
+                    key: key.clone(),
+                    client: client_color,
+                    pad: pad_color,
+                })
+            } else {
+                None
+            }
+        })
+        .collect();
+
+        let mut modes: Vec<ModeDiff> = local
+            .modes
+            .iter()
+            .filter_map(|mode| match pad.modes.iter().find(|m| m.key == mode.key) {
+                Some(remote) if remote == mode => None,
+                Some(remote) => Some(ModeDiff::Changed {
+                    key: mode.key.clone(),
+                    client: mode.clone(),
+                    pad: remote.clone(),
+                }),
+                None => Some(ModeDiff::ClientOnly {
+                    key: mode.key.clone(),
+                    client: mode.clone(),
+                }),
+            })
+            .collect();
+
+        modes.extend(
+            pad.modes
+                .iter()
+                .filter(|remote| !local.modes.iter().any(|m| m.key == remote.key))
+                .map(|remote| ModeDiff::PadOnly {
+                    key: remote.key.clone(),
+                    pad: remote.clone(),
+                }),
+        );
+
+        ConfigDiff { colors, modes }
+    }
+
+    /// Makes the pad match the local config.
+    pub fn push_to_pad(handle: PadRuntime, device: String, diff: &ConfigDiff) {
+        for color in diff.colors.iter() {
+            send_serial_command(
+                handle.clone(),
+                Some(device.clone()),
+                Message {
+                    message_type: MessageType::Command(CommandType::SetColor),
+                    data: serde_json::to_value(&color.client).ok(),
+                    id: None,
+                },
+            );
+        }
+
+        for mode in diff.modes.iter() {
+            let message = match mode {
+                ModeDiff::ClientOnly { client, .. } | ModeDiff::Changed { client, .. } => Message {
+                    message_type: MessageType::Command(CommandType::WriteMode),
+                    data: serde_json::to_value(client).ok(),
+                    id: None,
+                },
+                ModeDiff::PadOnly { key, .. } => Message {
+                    message_type: MessageType::Command(CommandType::DeleteMode),
+                    data: Some(json!({"key": key})),
+                    id: None,
+                },
+            };
+            send_serial_command(handle.clone(), Some(device.clone()), message);
+        }
+    }
+
+    /// Pushes what changed between two versions of the local config to every connected pad,
+    /// returning what each one was sent. The pads aren't asked for their config, so this relies
+    /// on them having matched `previous`.
+    pub fn push_changes(
+        handle: PadRuntime,
+        previous: &AppConfig,
+        current: &AppConfig,
+    ) -> HashMap<String, ConfigDiff> {
+        handle
+            .state()
+            .connected_devices()
+            .into_iter()
+            .map(|device| {
+                let diff = compute_diff(
+                    &current.device_view(&device),
+                    &previous.device_view(&device).to_pad(),
+                );
+                if !diff.is_empty() {
+                    push_to_pad(handle.clone(), device.clone(), &diff);
+                }
+                (device, diff)
+            })
+            .collect()
+    }
+
+    /// Makes the local config match the pad. Host-only key actions in modes the pad changed are
+    /// replaced, since the pad has no record of them; encoder bindings and focus rules are kept.
+    /// Modes only one side has are moved in or out of the device's mode set, so other pads keep
+    /// the modes the pad is missing.
+    pub fn pull_from_pad(
+        handle: PadRuntime,
+        device: String,
+        diff: &ConfigDiff,
+    ) -> Result<(), &'static str> {
+        let state = handle.state();
+        let mut config = state.lock_config().ok_or("Failed to lock state")?;
+        let mut undo = Vec::new();
+
+        for color in diff.colors.iter() {
+            undo.push(config.apply(ConfigEdit::SetColor {
+                color: color.pad.clone(),
+            }));
+        }
+
+        for mode in diff.modes.iter() {
+            match mode {
+                ModeDiff::PadOnly { key, pad } => {
+                    undo.push(config.apply(ConfigEdit::WriteMode {
+                        mode: Box::new(AppModeConfig::from_pad(pad.clone())),
+                    }));
+                    undo.push(config.apply(ConfigEdit::IncludeMode {
+                        device: device.clone(),
+                        key: key.clone(),
+                    }));
+                }
+                ModeDiff::Changed { key, pad, .. } => {
+                    let mut mode = AppModeConfig::from_pad(pad.clone());
+                    if let Some(local) = config.modes.iter().find(|m| m.key() == *key) {
+                        mode = config.relayer(mode.with_host_bindings(local));
+                    }
+                    undo.push(config.apply(ConfigEdit::WriteMode {
+                        mode: Box::new(mode),
+                    }));
+                }
+                ModeDiff::ClientOnly { key, .. } => {
+                    undo.push(config.apply(ConfigEdit::ExcludeMode {
+                        device: device.clone(),
+                        key: key.clone(),
+                    }));
+                }
+            }
+        }
+
+        config.save(handle.clone());
+        undo.reverse();
+        if let Some(mut journal) = state.lock_journal() {
+            journal.record("sync.pull", undo);
+        }
+        Ok(())
+    }
+
+    /// Fetches the device's config, diffs it against the local view of that device and settles it
+    /// with `policy`, falling back to the configured policy. While includes failed to load the
+    /// local view is missing their modes, so the fallback is only to prompt.
+    pub fn reconcile(
+        handle: PadRuntime,
+        device: String,
+        policy: Option<SyncPolicy>,
+    ) -> Result<ConfigDiff, &'static str> {
+        let pad = fetch_pad_config(handle.clone(), Some(device.clone()))?;
+        let (diff, configured) = {
+            let state = handle.state();
+            let config = state.lock_config().ok_or("Failed to lock state")?;
+            let configured = if config.include_errors.is_empty() {
+                config.sync_policy.clone()
+            } else {
+                SyncPolicy::Prompt
+            };
+            (compute_diff(&config.device_view(&device), &pad), configured)
+        };
+        let policy = policy.unwrap_or(configured);
+
+        let applied = if diff.is_empty() {
+            false
+        } else {
+            match policy {
+                SyncPolicy::ClientWins => {
+                    push_to_pad(handle.clone(), device.clone(), &diff);
+                    true
+                }
+                SyncPolicy::PadWins => {
+                    pull_from_pad(handle.clone(), device.clone(), &diff)?;
+                    true
+                }
+                SyncPolicy::Prompt => false,
+            }
+        };
+
+        handle.emit(
+            "ratpad://sync",
+            SyncEvent {
+                device,
+                policy,
+                diff: diff.clone(),
+                applied,
+            },
+        );
+        Ok(diff)
+    }
+
+    /// Reconciles each pad once its handshake is done, as reading its config depends on the
+    /// protocol it speaks.
+    pub fn start_sync_listener(runtime: &PadRuntime) {
+        let handle = runtime.clone();
+        runtime.listen("ratpad://capabilities", move |payload| {
+            let Some(Ok(CapabilitiesEvent { device, .. })) =
+                payload.map(serde_json::from_str::<CapabilitiesEvent>)
+            else {
+                return;
+            };
+            let sync_handle = handle.clone();
+            spawn_blocking(move || {
+                if let Err(reason) = reconcile(sync_handle.clone(), device.clone(), None) {
+                    sync_handle.emit(
+                        "ratpad://sync/failed",
+                        SyncFailedEvent {
+                            device,
+                            reason: reason.to_string(),
+                        },
+                    );
+                }
+            });
+        });
+    }
+}
@@ -0,0 +1,222 @@
+pub mod pad_transport {
+    use std::{
+        collections::HashMap,
+        future::Future,
+        io::{self, ErrorKind},
+        pin::Pin,
+        sync::{Mutex, OnceLock},
+    };
+
+    use tokio::{
+        io::{duplex, AsyncRead, AsyncWrite, DuplexStream},
+        net::TcpStream,
+        sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    };
+    use tokio_serial::{new, SerialPortBuilderExt};
+
+    /// A byte stream to a pad, whatever it runs over.
+    pub trait PadStream: AsyncRead + AsyncWrite + Send + Unpin {}
+    impl<T: AsyncRead + AsyncWrite + Send + Unpin> PadStream for T {}
+
+    pub type BoxedStream = Box<dyn PadStream>;
+    pub type OpenFuture<'a> = Pin<Box<dyn Future<Output = io::Result<BoxedStream>> + Send + 'a>>;
+
+    /// A way of reaching a pad. `open` is called again on every reconnect attempt.
+    pub trait Transport: Send + Sync {
+        fn open(&self) -> OpenFuture<'_>;
+
+        /// The address this transport was parsed from.
+        fn address(&self) -> String;
+    }
+
+    pub struct SerialTransport {
+        pub port: String,
+        pub rate: u32,
+    }
+
+    impl Transport for SerialTransport {
+        fn open(&self) -> OpenFuture<'_> {
+            Box::pin(async move {
+                let stream = new(self.port.clone(), self.rate)
+                    .open_native_async()
+                    .map_err(io::Error::from)?;
+                Ok(Box::new(stream) as BoxedStream)
+            })
+        }
+
+        fn address(&self) -> String {
+            format!("serial://{}?baud={}", self.port, self.rate)
+        }
+    }
+
+    pub struct TcpTransport {
+        pub host: String,
+    }
+
+    impl Transport for TcpTransport {
+        fn open(&self) -> OpenFuture<'_> {
+            Box::pin(async move {
+                let stream = TcpStream::connect(self.host.as_str()).await?;
+                stream.set_nodelay(true)?;
+                Ok(Box::new(stream) as BoxedStream)
+            })
+        }
+
+        fn address(&self) -> String {
+            format!("tcp://{}", self.host)
+        }
+    }
+
+    fn memory_peers() -> &'static Mutex<HashMap<String, UnboundedSender<DuplexStream>>> {
+        static PEERS: OnceLock<Mutex<HashMap<String, UnboundedSender<DuplexStream>>>> =
+            OnceLock::new();
+        PEERS.get_or_init(|| Mutex::new(HashMap::new()))
+    }
+
+    /// Accepts in-process connections to `mem://<name>`. Each connection arrives as the pad's end
+    /// of a pipe; replaces any earlier listener on the same name.
+    pub fn bind_memory(name: &str) -> UnboundedReceiver<DuplexStream> {
+        let (tx, rx) = unbounded_channel::<DuplexStream>();
+        if let Ok(mut peers) = memory_peers().lock() {
+            peers.insert(name.to_string(), tx);
+        }
+        rx
+    }
+
+    pub struct MemoryTransport {
+        pub name: String,
+    }
+
+    impl Transport for MemoryTransport {
+        fn open(&self) -> OpenFuture<'_> {
+            Box::pin(async move {
+                let (client, pad) = duplex(4096);
+                let delivered = memory_peers()
+                    .lock()
+                    .ok()
+                    .and_then(|peers| peers.get(&self.name).map(|peer| peer.send(pad).is_ok()))
+                    .unwrap_or(false);
+
+                if delivered {
+                    Ok(Box::new(client) as BoxedStream)
+                } else {
+                    Err(io::Error::new(
+                        ErrorKind::ConnectionRefused,
+                        format!("Nothing is listening on mem://{}", self.name),
+                    ))
+                }
+            })
+        }
+
+        fn address(&self) -> String {
+            format!("mem://{}", self.name)
+        }
+    }
+
+    /// Parses a pad address: `serial:///dev/ttyACM1?baud=115200`, `tcp://host:port` or
+    /// `mem://name`. Anything without a scheme is taken as a serial port at `rate`, which is how
+    /// ports were saved before addresses existed.
+    pub fn parse_address(address: &str, rate: u32) -> Result<Box<dyn Transport>, String> {
+        let Some((scheme, rest)) = address.split_once("://") else {
+            return Ok(Box::new(SerialTransport {
+                port: address.to_string(),
+                rate,
+            }));
+        };
+
+        let (target, query) = rest.split_once('?').unwrap_or((rest, ""));
+        if target.is_empty() {
+            return Err(format!("Missing target in {address}"));
+        }
+
+        match scheme {
+            "serial" => {
+                let baud = query
+                    .split('&')
+                    .find_map(|pair| pair.strip_prefix("baud="))
+                    .map(|value| {
+                        value
+                            .parse::<u32>()
+                            .map_err(|_| format!("Invalid baud rate in {address}"))
+                    })
+                    .transpose()?;
+                Ok(Box::new(SerialTransport {
+                    port: target.to_string(),
+                    rate: baud.unwrap_or(rate),
+                }))
+            }
+            "tcp" => Ok(Box::new(TcpTransport {
+                host: target.to_string(),
+            })),
+            "mem" => Ok(Box::new(MemoryTransport {
+                name: target.to_string(),
+            })),
+            _ => Err(format!("Unknown transport: {scheme}")),
+        }
+    }
+
+    /// The serial port an address points at, if it is a serial address.
+    pub fn serial_port(address: &str) -> Option<String> {
+        match address.split_once("://") {
+            None => Some(address.to_string()),
+            Some(("serial", rest)) => Some(rest.split('?').next().unwrap_or(rest).to_string()),
+            Some(_) => None,
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        use super::*;
+
+        #[test]
+        fn parses_addresses() {
+            assert_eq!(
+                parse_address("/dev/ttyACM0", 9600).unwrap().address(),
+                "serial:///dev/ttyACM0?baud=9600"
+            );
+            assert_eq!(
+                parse_address("serial:///dev/ttyACM1?baud=115200", 9600)
+                    .unwrap()
+                    .address(),
+                "serial:///dev/ttyACM1?baud=115200"
+            );
+            assert_eq!(
+                parse_address("tcp://localhost:4000", 0).unwrap().address(),
+                "tcp://localhost:4000"
+            );
+            assert_eq!(
+                parse_address("mem://pad", 0).unwrap().address(),
+                "mem://pad"
+            );
+            assert!(parse_address("serial:///dev/ttyACM1?baud=fast", 0).is_err());
+            assert!(parse_address("tcp://", 0).is_err());
+            assert!(parse_address("udp://localhost:4000", 0).is_err());
+        }
+
+        #[test]
+        fn finds_serial_ports() {
+            assert_eq!(serial_port("/dev/ttyACM0").as_deref(), Some("/dev/ttyACM0"));
+            assert_eq!(
+                serial_port("serial:///dev/ttyACM1?baud=9600").as_deref(),
+                Some("/dev/ttyACM1")
+            );
+            assert_eq!(serial_port("tcp://localhost:4000"), None);
+        }
+
+        #[tokio::test]
+        async fn connects_memory_peers() {
+            let transport = parse_address("mem://transport-test", 0).unwrap();
+            assert!(transport.open().await.is_err());
+
+            let mut peers = bind_memory("transport-test");
+            let mut client = transport.open().await.unwrap();
+            let mut pad = peers.recv().await.unwrap();
+            client.write_all(b"ping").await.unwrap();
+            let mut received = [0u8; 4];
+            pad.read_exact(&mut received).await.unwrap();
+            assert_eq!(&received, b"ping");
+        }
+    }
+}
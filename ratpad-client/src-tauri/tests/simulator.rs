@@ -0,0 +1,131 @@
+//! Runs the client's services against the simulated pad over a pty, the way the daemon and
+//! `ratpad-sim` are used together by hand.
+#![cfg(unix)]
+
+use std::{env::temp_dir, fs::remove_dir_all, process, time::Duration};
+
+use ratpad::{
+    serial_client::{send_listener_command, ListenerCommand, SerialEvent},
+    util::{
+        command_handler::{execute, CommandReturnTypes, CommandTypes},
+        pad_runtime::{start_runtime, PadRuntime},
+        pad_simulator::{PtyPad, SimulatedPad},
+    },
+};
+use serde_json::json;
+use tokio::{
+    sync::mpsc::{unbounded_channel, UnboundedReceiver},
+    task::spawn_blocking,
+    time::timeout,
+};
+
+const WAIT: Duration = Duration::from_secs(5);
+
+/// A running client with its config in a fresh directory, and the serial events it publishes.
+fn client(name: &str) -> (PadRuntime, UnboundedReceiver<SerialEvent>) {
+    let dir = temp_dir().join(format!("ratpad-{name}-{}", process::id()));
+    let _ = remove_dir_all(&dir);
+    let runtime = PadRuntime::new(dir);
+    let (tx, rx) = unbounded_channel::<SerialEvent>();
+    runtime.listen("ratpad://serial", move |payload| {
+        if let Some(event) = payload.and_then(|p| serde_json::from_str(p).ok()) {
+            let _ = tx.send(event);
+        }
+    });
+    start_runtime(&runtime);
+    (runtime, rx)
+}
+
+/// Connects `runtime` to `pad` as `device` and waits for the connection to come up.
+async fn connect(
+    runtime: &PadRuntime,
+    events: &mut UnboundedReceiver<SerialEvent>,
+    pad: &PtyPad,
+    device: &str,
+) {
+    send_listener_command(
+        runtime.clone(),
+        ListenerCommand::Connect {
+            device: device.to_string(),
+            new_port: pad.path().display().to_string(),
+            new_rate: 115200,
+        },
+    );
+    loop {
+        match timeout(WAIT, events.recv()).await.unwrap().unwrap() {
+            SerialEvent::Connect { device: connected } if connected == device => return,
+            SerialEvent::Error { reason, .. } => panic!("Unable to connect: {reason}"),
+            _ => continue,
+        }
+    }
+}
+
+/// Runs a command the way the control socket does, off the async workers.
+async fn run(runtime: &PadRuntime, command: CommandTypes) -> Result<CommandReturnTypes, String> {
+    let runtime = runtime.clone();
+    timeout(WAIT, spawn_blocking(move || execute(runtime, command)))
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reads_and_writes_the_simulated_pad() {
+    let (runtime, mut events) = client("simulator");
+    let pad = PtyPad::open(SimulatedPad::new(None), None).unwrap();
+    connect(&runtime, &mut events, &pad, "sim").await;
+
+    let device = Some("sim".to_string());
+    let Ok(CommandReturnTypes::PadGetConfig { config }) = run(
+        &runtime,
+        CommandTypes::PadGetConfig {
+            device: device.clone(),
+        },
+    )
+    .await
+    else {
+        panic!("Expected the pad's config");
+    };
+    let before = pad.with_pad(|p| p.config().modes.clone()).unwrap();
+    assert_eq!(config.modes, before);
+
+    let mode = serde_json::from_value(json!({
+        "key": "editor",
+        "title": "Editor",
+        "title_short": "EDT",
+        "color": [0, 128, 255],
+        "keys": [
+            {"label": "Save", "action": {"type": "keypress", "key": "CTRL+S"}, "color": null},
+            null,
+        ],
+    }))
+    .unwrap();
+    let written = run(
+        &runtime,
+        CommandTypes::ConfWriteMode {
+            mode,
+            device: device.clone(),
+        },
+    )
+    .await;
+    assert!(matches!(written, Ok(CommandReturnTypes::ConfWriteMode {})));
+
+    // The pad answers in order, so reading the config back also waits for the write to land.
+    let Ok(CommandReturnTypes::PadGetConfig { config }) =
+        run(&runtime, CommandTypes::PadGetConfig { device }).await
+    else {
+        panic!("Expected the pad's config");
+    };
+    let editor = config.modes.iter().find(|m| m.key == "editor").unwrap();
+    assert_eq!(editor.title, "Editor");
+    assert_eq!(editor.color, Some((0, 128, 255)));
+    assert_eq!(editor.keys[0].as_ref().unwrap().label, "Save");
+    assert!(editor.keys[1].is_none());
+    assert_eq!(
+        pad.with_pad(|p| p.config().modes.clone()).unwrap(),
+        config.modes
+    );
+
+    send_listener_command(runtime.clone(), ListenerCommand::Quit);
+    let _ = remove_dir_all(runtime.config_dir());
+}
@@ -1,4 +1,5 @@
-import { AppModeConfig } from "./mode";
+import { AppModeConfig, Delivery } from "./mode";
+import { DeviceIdentity } from "./serial";
 
 export type Color = [number, number, number];
 
@@ -9,13 +10,81 @@ export type AppColorsConfig = {
     brightness: number;
 };
 
+export type DeviceConfig = {
+    device: string;
+    port: string | null;
+    rate: number | null;
+    modes: string[] | null;
+    identity: DeviceIdentity | null;
+};
+
 export type AppConfig = {
-    device_port: string | null;
-    device_rate: number | null;
+    schema_version: number;
+    devices: DeviceConfig[];
+    colors: AppColorsConfig;
+    modes: AppModeConfig[];
+    sync_policy: SyncPolicy;
+    auto_switch: boolean;
+    keyboard_layout: KeyboardLayout;
+    text_fallback: TextFallback;
+    delivery: Delivery;
+    profile: string;
+    profiles: { [name: string]: ProfileConfig };
+    include?: string[];
+};
+
+export type ConfigFormat = "json" | "toml" | "yaml";
+
+export type ProfileConfig = {
     colors: AppColorsConfig;
     modes: AppModeConfig[];
 };
 
+export type KeyboardLayout = "us" | "uk" | "de" | "fr";
+
+export type TextFallback = "unicode_input" | "skip";
+
+export type SyncPolicy = "client_wins" | "pad_wins" | "prompt";
+
+export type SetColorType =
+    | { key: "next" | "previous" | "select"; color: Color }
+    | { key: "brightness"; color: number };
+
+export type PadModeKey = {
+    label: string;
+    keys: string | null;
+    color: Color | null;
+};
+
+export type PadModeConfig = {
+    key: string;
+    title: string;
+    title_short: string;
+    keys: (PadModeKey | null)[];
+    color: Color | null;
+};
+
+export type ColorDiff = {
+    key: "next" | "previous" | "select" | "brightness";
+    client: SetColorType;
+    pad: SetColorType;
+};
+
+export type ModeDiff =
+    | { change: "client_only"; key: string; client: PadModeConfig }
+    | { change: "pad_only"; key: string; pad: PadModeConfig }
+    | {
+          change: "changed";
+          key: string;
+          client: PadModeConfig;
+          pad: PadModeConfig;
+      };
+
+export type ConfigDiff = {
+    colors: ColorDiff[];
+    modes: ModeDiff[];
+};
+
 export enum ConnectionState {
     Connected = "Connected",
     Disconnected = "Disconnected",
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type Key = { code: number, name: string, 
+/**
+ * 0-based action slot, negative for the navigation keys.
+ */
+action: number, };
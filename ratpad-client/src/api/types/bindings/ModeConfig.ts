@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ModeKey } from "./ModeKey";
+
+export type ModeConfig = { key: string, title: string, title_short: string, keys: Array<ModeKey | null>, color: [number, number, number] | null, };
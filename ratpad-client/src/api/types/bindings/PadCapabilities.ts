@@ -0,0 +1,10 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * What the connected pad reported about itself during the handshake.
+ */
+export type PadCapabilities = { firmware: string, protocol: number, commands: Array<string>, keys: number, 
+/**
+ * Encoder reading when the pad answered, so the first rotation has a baseline.
+ */
+encoder?: number, };
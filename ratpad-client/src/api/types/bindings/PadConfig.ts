@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ColorsConfig } from "./ColorsConfig";
+import type { ModeConfig } from "./ModeConfig";
+
+export type PadConfig = { colors: ColorsConfig, modes: Array<ModeConfig>, };
@@ -0,0 +1,10 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Key } from "./Key";
+import type { LogLevel } from "./LogLevel";
+import type { PadCapabilities } from "./PadCapabilities";
+import type { PadConfig } from "./PadConfig";
+
+/**
+ * A message from the pad, decoded into what it means.
+ */
+export type PadEvent = { "type": "key.pressed", mode: string | null, key: Key, } | { "type": "encoder.switch", mode: string | null, pressed: boolean, } | { "type": "encoder.rotated", mode: string | null, value: number, delta: number, } | { "type": "mode.changed", mode: string | null, } | { "type": "log", content: string, level: LogLevel, } | { "type": "config", config: PadConfig, } | { "type": "connect" } | { "type": "disconnect" } | { "type": "capabilities", capabilities: PadCapabilities, } | { "type": "unknown", message_type: string, data: unknown, };
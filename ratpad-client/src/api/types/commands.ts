@@ -1,6 +1,8 @@
 import { invoke } from "@tauri-apps/api";
-import { ConnectionState, PortInfo } from "./serial";
-import { AppModeConfig } from "./mode";
+import { DeviceState, PortInfo } from "./serial";
+import { AppModeConfig, Delivery } from "./mode";
+import { ConfigDiff, ConfigFormat, KeyboardLayout, SyncPolicy, TextFallback } from "./app";
+import { ConfigEvent } from "./events";
 
 type CommandType<Type extends `${string}.${string}`, Data> = {
     type: Type;
@@ -20,45 +22,154 @@ type CommandSpec<
     returnType: CommandReturnType<Type, Return>;
 };
 
+// Device-scoped commands target one pad by serial number, or every connected pad when omitted.
+export type DeviceTarget = { device?: string | null };
+
 export type SerialConnect = CommandSpec<
     "serial.connect",
-    { port: string; rate: number }
+    { port: string; rate: number } & DeviceTarget
 >;
 
-export type SerialDisconnect = CommandSpec<"serial.disconnect">;
+export type SerialDisconnect = CommandSpec<"serial.disconnect", DeviceTarget>;
 
-export type SerialPortList = CommandSpec<"serial.list_ports", null, PortInfo[]>;
+export type SerialPortList = CommandSpec<
+    "serial.list_ports",
+    DeviceTarget,
+    PortInfo[]
+>;
 
 export type SerialConnectionState = CommandSpec<
     "serial.get_state",
-    null,
-    { connected: ConnectionState; port?: string; rate?: number }
+    DeviceTarget,
+    { devices: DeviceState[] }
 >;
 
 export type GetConfig = CommandSpec<
     "config.get_config",
-    {},
+    DeviceTarget,
     { config: AppModeConfig }
 >;
 
+export type ConfLastEvent = CommandSpec<
+    "config.last_event",
+    {},
+    { event: ConfigEvent | null }
+>;
+
 export type SetColorType =
     | { key: "next" | "previous" | "select"; color: [number, number, number] }
     | { key: "brightness"; color: number };
 
-export type ConfSetColor = CommandSpec<"pad.set_color", SetColorType>;
+export type ConfSetColor = CommandSpec<
+    "pad.set_color",
+    SetColorType & DeviceTarget
+>;
 
 export type ConfWriteMode = CommandSpec<
     "config.write_mode",
-    { mode: AppModeConfig }
+    { mode: AppModeConfig } & DeviceTarget
+>;
+
+export type ConfDeleteMode = CommandSpec<
+    "config.delete_mode",
+    { key: string } & DeviceTarget
+>;
+
+export type ConfClearModes = CommandSpec<"config.clear_modes", DeviceTarget>;
+
+export type ConfUndo = CommandSpec<
+    "config.undo",
+    {},
+    { command: string; diffs: { [device: string]: ConfigDiff } }
+>;
+
+export type ConfRedo = CommandSpec<
+    "config.redo",
+    {},
+    { command: string; diffs: { [device: string]: ConfigDiff } }
+>;
+
+export type ConfSetDeviceModes = CommandSpec<
+    "config.set_device_modes",
+    { device: string; modes: string[] | null }
+>;
+
+export type PadSetHome = CommandSpec<"pad.set_home", DeviceTarget>;
+
+export type PadSetMode = CommandSpec<
+    "pad.set_mode",
+    { mode: string } & DeviceTarget
+>;
+
+export type ConfSetSyncPolicy = CommandSpec<
+    "config.set_sync_policy",
+    { policy: SyncPolicy }
+>;
+
+export type ConfSetAutoSwitch = CommandSpec<
+    "config.set_auto_switch",
+    { enabled: boolean }
+>;
+
+export type ConfSetKeyboardLayout = CommandSpec<
+    "config.set_keyboard_layout",
+    { layout: KeyboardLayout; fallback?: TextFallback | null }
+>;
+
+export type ConfSetDelivery = CommandSpec<
+    "config.set_delivery",
+    { delivery: Delivery }
+>;
+
+export type ConfSetFormat = CommandSpec<
+    "config.set_format",
+    { format: ConfigFormat },
+    { path: string }
+>;
+
+export type KeysComplete = CommandSpec<
+    "keys.complete",
+    { keys: string },
+    { chord: string | null; error: string | null; completions: string[] }
 >;
 
-export type ConfDeleteMode = CommandSpec<"config.delete_mode", { key: string }>;
+export type SyncGetDiff = CommandSpec<
+    "sync.get_diff",
+    DeviceTarget,
+    { diff: ConfigDiff }
+>;
+
+export type SyncApply = CommandSpec<
+    "sync.apply",
+    { policy: Exclude<SyncPolicy, "prompt"> } & DeviceTarget,
+    { diff: ConfigDiff }
+>;
 
-export type ConfClearModes = CommandSpec<"config.clear_modes">;
+export type ProfileList = CommandSpec<
+    "profile.list",
+    {},
+    { active: string; profiles: string[] }
+>;
 
-export type PadSetHome = CommandSpec<"pad.set_home">;
+export type ProfileCreate = CommandSpec<"profile.create", { name: string }>;
 
-export type PadSetMode = CommandSpec<"pad.set_mode", { mode: string }>;
+export type ProfileClone = CommandSpec<
+    "profile.clone",
+    { source: string; name: string }
+>;
+
+export type ProfileRename = CommandSpec<
+    "profile.rename",
+    { name: string; new_name: string }
+>;
+
+export type ProfileDelete = CommandSpec<"profile.delete", { name: string }>;
+
+export type ProfileActivate = CommandSpec<
+    "profile.activate",
+    { name: string },
+    { diffs: { [device: string]: { Ok: ConfigDiff } | { Err: string } } }
+>;
 
 export class CommandResult<T extends CommandSpec> {
     public constructor(
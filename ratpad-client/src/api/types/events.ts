@@ -1,6 +1,112 @@
+import { ConfigDiff, SyncPolicy } from "./app";
+import { DeviceIdentity } from "./serial";
+import { EncoderInput } from "./mode";
+import { PadEvent } from "./bindings/PadEvent";
+
+export type { PadEvent };
+
 export type EventType = "config" | "connect" | "disconnect" | "event" | "log";
 
 export type SerialEvent =
-    | { type: "connect" }
-    | { type: "disconnect" }
-    | { type: "event"; event_type: EventType; data: any };
+    | { Connect: { device: string } }
+    | { Disconnect: { device: string } }
+    | { Error: { device: string; reason: string } }
+    | {
+          Event: {
+              device: string;
+              message: {
+                  message_type: EventType;
+                  data: any;
+                  id: number | null;
+              };
+              event: PadEvent;
+          };
+      };
+
+export type ActionSource = { index: number } | { encoder: EncoderInput };
+
+export type ActionEvent = ActionSource &
+    (
+        | { type: "action.unbound"; mode: string }
+        | { type: "action.keypress"; mode: string; key: string }
+        | { type: "action.typed"; mode: string; text: string }
+        | { type: "action.started"; mode: string; execute: string }
+        | {
+              type: "action.finished";
+              mode: string;
+              execute: string;
+              success: boolean;
+              code: number | null;
+              stdout: string;
+              stderr: string;
+          }
+        | { type: "action.timed_out"; mode: string; execute: string }
+        | {
+              type: "action.failed";
+              mode: string;
+              execute: string;
+              reason: string;
+          }
+        | { type: "action.macro_started"; mode: string }
+        | { type: "action.macro_finished"; mode: string }
+        | { type: "action.macro_cancelled"; mode: string }
+        | { type: "action.input_failed"; mode: string; reason: string }
+    );
+
+export type SyncEvent = {
+    device: string;
+    policy: SyncPolicy;
+    diff: ConfigDiff;
+    applied: boolean;
+};
+
+export type SyncFailedEvent = {
+    device: string;
+    reason: string;
+};
+
+export type HotplugEvent =
+    | {
+          type: "hotplug.arrived";
+          port: string;
+          identity: DeviceIdentity;
+          device: string | null;
+      }
+    | {
+          type: "hotplug.removed";
+          port: string;
+          identity: DeviceIdentity | null;
+          device: string | null;
+      };
+
+export type FocusedWindow = {
+    class: string[];
+    title: string;
+    process: string | null;
+};
+
+export type FocusEvent = {
+    window: FocusedWindow | null;
+    mode: string | null;
+    applied: boolean;
+    error: string | null;
+};
+
+export type ConfigEvent =
+    | {
+          type: "recovered";
+          backup: string;
+          reason: string;
+          corrupt: string | null;
+      }
+    | { type: "reset"; reason: string; corrupt: string | null }
+    | { type: "include_failed"; reason: string }
+    | { type: "save_failed"; reason: string }
+    | { type: "backup_failed"; reason: string }
+    | { type: "reloaded"; diff: ConfigDiff }
+    | { type: "reload_failed"; reason: string };
+
+export type ServiceEvent = {
+    service: string;
+    reason: string;
+};
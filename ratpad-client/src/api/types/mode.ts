@@ -1,7 +1,31 @@
 export type AppKeyAction =
     | { type: "none" }
-    | { type: "keypress"; key: string }
-    | { type: "command"; execute: string; args: string[] | null };
+    | { type: "keypress"; key: string; delivery?: Delivery | null }
+    | {
+          type: "command";
+          execute: string;
+          args: string[] | null;
+          cwd?: string | null;
+          env?: { [key: string]: string } | null;
+          timeout?: number | null;
+      }
+    | { type: "macro"; steps: AppMacroStep[]; delivery?: Delivery | null }
+    | { type: "type_text"; text: string; delivery?: Delivery | null };
+
+export type Delivery = "pad_hid" | "host_uinput";
+
+export type MouseButton = "left" | "right" | "middle";
+
+export type AppMacroStep =
+    | { type: "chord"; keys: string }
+    | { type: "key_down"; keys: string }
+    | { type: "key_up"; keys: string }
+    | { type: "text"; text: string }
+    | { type: "delay"; ms: number }
+    | { type: "mouse_move"; x: number; y: number }
+    | { type: "scroll"; amount: number }
+    | { type: "click"; button: MouseButton }
+    | { type: "action"; action: AppKeyAction };
 
 export type AppKeyConfig = {
     label: string;
@@ -9,10 +33,31 @@ export type AppKeyConfig = {
     color: [number, number, number] | null;
 };
 
+export type EncoderInput =
+    | "clockwise"
+    | "counter_clockwise"
+    | "press"
+    | "held_clockwise"
+    | "held_counter_clockwise";
+
+export type AppEncoderConfig = {
+    [input in EncoderInput]?: AppKeyAction | null;
+};
+
+export type FocusRule = {
+    class?: string | null;
+    title?: string | null;
+    process?: string | null;
+};
+
 export type AppModeConfig = {
     key: string;
     title: string;
     title_short: string;
     color: [number, number, number] | null;
     keys: (AppKeyConfig | null)[];
+    encoder?: AppEncoderConfig | null;
+    focus?: FocusRule[];
+    priority?: number;
+    parent?: string | null;
 };
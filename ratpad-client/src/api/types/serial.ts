@@ -1,3 +1,5 @@
+import { PadCapabilities } from "./bindings/PadCapabilities";
+
 export type UsbPortType = {
     vid: number;
     pid: number;
@@ -6,6 +8,13 @@ export type UsbPortType = {
     product?: string;
 };
 
+export type DeviceIdentity = {
+    vid: number;
+    pid: number;
+    serial_number: string | null;
+    interface: string | null;
+};
+
 export type PortType =
     | "PciPort"
     | "BluetoothPort"
@@ -24,3 +33,13 @@ export enum ConnectionState {
     Waiting = "Waiting",
     Disconnected = "Disconnected",
 }
+
+export type { PadCapabilities };
+
+export type DeviceState = {
+    device: string;
+    connection: ConnectionState;
+    port: string | null;
+    rate: number | null;
+    capabilities: PadCapabilities | null;
+};
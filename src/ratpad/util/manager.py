@@ -1,5 +1,6 @@
 import time
 from adafruit_macropad import MacroPad, Keycode
+from adafruit_hid.mouse import Mouse
 import board
 import busio
 import json
@@ -15,13 +16,34 @@ except ImportError:
 
 import traceback
 
+FIRMWARE_VERSION = "0.1.0"
+PROTOCOL_VERSION = 1
+SUPPORTED_COMMANDS = [
+    "hello",
+    "set_color",
+    "write_mode",
+    "delete_mode",
+    "clear_modes",
+    "set_mode",
+    "set_home",
+    "read_config",
+    "send_keys",
+    "send_sequence",
+    "send_mouse",
+]
+
 
 class CommandPacket:
     def __init__(self, data: bytes) -> None:
         decoded = data.decode()
-        command = decoded.split(":")[0]
+        header = decoded.split(":")[0]
         rest = ":".join(decoded.split(":")[1:])
+        command, _, request_id = header.partition("#")
         self.command = command.lower()
+        try:
+            self.id = int(request_id) if request_id else None
+        except ValueError:
+            self.id = None
         if len(rest) > 0:
             try:
                 self.data = json.loads(rest.strip().strip(";"))
@@ -42,9 +64,12 @@ class PadManager:
         self.modes = ModeManager()
         self.display = DisplayManager(self.pad, self.modes)
 
-    def send_packet(self, type: str, data: Any | None = None):
+    def send_packet(
+        self, type: str, data: Any | None = None, request_id: int | None = None
+    ):
         self.serial.write(
             type.upper().encode()
+            + (b"#" + str(request_id).encode() if request_id != None else b"")
             + b":"
             + (json.dumps(data).encode() if data else b"")
             + b";\n"
@@ -75,9 +100,13 @@ class PadManager:
                 "value": encoder_value,
             }
         elif new_mode != None:
-            packet = {"mode": self.mode.key, "type": "mode"}
+            packet = {
+                "mode": self.mode.key,
+                "type": "mode",
+                "encoder": self.encoder_rotation,
+            }
         else:
-            packet = {"mode": None, "type": "mode"}
+            packet = {"mode": None, "type": "mode", "encoder": self.encoder_rotation}
 
         self.send_packet("event", packet)
 
@@ -100,7 +129,31 @@ class PadManager:
                         command = self.parse_packet(line)
                         self.log(f"Parsing command: {command.command}")
                         try:
-                            if command.command == "set_color":
+                            if command.command == "hello":
+                                client_protocol = (
+                                    command.data.get("protocol", None)
+                                    if command.data
+                                    else None
+                                )
+                                if client_protocol != PROTOCOL_VERSION:
+                                    self.log(
+                                        f"Client protocol {client_protocol} differs from firmware protocol {PROTOCOL_VERSION}",
+                                        level="warning",
+                                    )
+                                self.send_packet(
+                                    "capabilities",
+                                    data={
+                                        "firmware": FIRMWARE_VERSION,
+                                        "protocol": PROTOCOL_VERSION,
+                                        "commands": SUPPORTED_COMMANDS,
+                                        "keys": len(
+                                            [i for i in range(12) if not Keys.get(i).special]
+                                        ),
+                                        "encoder": self.encoder_rotation,
+                                    },
+                                    request_id=command.id,
+                                )
+                            elif command.command == "set_color":
                                 if (
                                     command.data.get("key", None)
                                     in self.modes.colors.keys()
@@ -144,7 +197,49 @@ class PadManager:
                                 self.display.set_mode(None)
                                 self.send_event(new_mode=None)
                             elif command.command == "read_config":
-                                self.send_packet("config", data=self.modes.as_dict())
+                                self.send_packet(
+                                    "config",
+                                    data=self.modes.as_dict(),
+                                    request_id=command.id,
+                                )
+                            elif command.command == "send_keys":
+                                keycodes = (
+                                    [
+                                        getattr(Keycode, i.upper())
+                                        for i in command.data["keys"].split("+")
+                                    ]
+                                    if command.data.get("keys", None)
+                                    else []
+                                )
+                                action = command.data.get("action", "send")
+                                if action == "press":
+                                    self.pad.keyboard.press(*keycodes)
+                                elif action == "release":
+                                    if keycodes:
+                                        self.pad.keyboard.release(*keycodes)
+                                    else:
+                                        self.pad.keyboard.release_all()
+                                elif keycodes:
+                                    self.pad.keyboard.send(*keycodes)
+                            elif command.command == "send_sequence":
+                                for chord in command.data.get("chords", []):
+                                    self.pad.keyboard.send(
+                                        *[getattr(Keycode, i.upper()) for i in chord.split("+")]
+                                    )
+                            elif command.command == "send_mouse":
+                                if command.data.get("click", None):
+                                    self.pad.mouse.click(
+                                        getattr(
+                                            Mouse,
+                                            command.data["click"].upper() + "_BUTTON",
+                                        )
+                                    )
+                                else:
+                                    self.pad.mouse.move(
+                                        x=command.data.get("x", 0),
+                                        y=command.data.get("y", 0),
+                                        wheel=command.data.get("wheel", 0),
+                                    )
                         except:
                             self.log(traceback.format_exc(), level="error")
 
@@ -204,13 +299,16 @@ class PadManager:
                                         new_mode=self.mode.key if self.mode else None
                                     )
 
-                if self.mode:
-                    if self.pad.encoder_switch != self.encoder_switch:
-                        self.encoder_switch = self.pad.encoder_switch
+                # Readings are tracked outside modes too, so the client's rotation deltas don't
+                # pick up turns made while no mode was active.
+                if self.pad.encoder_switch != self.encoder_switch:
+                    self.encoder_switch = self.pad.encoder_switch
+                    if self.mode:
                         self.send_event(encoder_switch=self.encoder_switch)
 
-                    if self.pad.encoder != self.encoder_rotation:
-                        self.encoder_rotation = self.pad.encoder
+                if self.pad.encoder != self.encoder_rotation:
+                    self.encoder_rotation = self.pad.encoder
+                    if self.mode:
                         self.send_event(encoder_value=self.encoder_rotation)
 
                 time.sleep(0.1)